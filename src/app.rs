@@ -1,38 +1,192 @@
 use crate::{
-    color::Rgb,
-    render::Renderer,
+    color::{palettes, Palette, Rgb, Shade, Transfer},
+    render::{Kernel, Renderer},
+    rules::{Bailout, BailoutTest, OrbitTrap, RootFindingParams, RuleParams, TrapShape, MAX_ITERS},
     simd::{Array, SimdComplex},
-    utils::{Complex, Direction, FnSync},
+    utils::{Complex, Direction},
     view::ComplexPlaneView,
 };
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
 use minifb_fonts::{font6x8, FbFontRenderer};
 use std::{
     thread,
     time::{Duration, Instant},
 };
 
-pub struct FractalExplorerApp<F>
-where
-    F: FnSync(SimdComplex, Complex) -> Array<Rgb>,
-{
+/// Values that the app computes for each pixel. They are turned into colors once
+/// the whole frame is computed, so that the coloring can depend on the frame.
+pub trait PixelValue: Copy + Send {
+    /// Whether the pixels are colored with the palette, so that the palette controls apply.
+    const USES_PALETTE: bool = true;
+
+    /// Appends the colors of the pixels of a frame to `frame_buffer`.
+    fn color_frame(
+        values: &[Self],
+        palette: &Palette,
+        transfer: Transfer,
+        frame_buffer: &mut Vec<u32>,
+    );
+}
+
+/// Colors of their own, which ignore the palette.
+impl PixelValue for Rgb {
+    const USES_PALETTE: bool = false;
+
+    fn color_frame(values: &[Self], _: &Palette, _: Transfer, frame_buffer: &mut Vec<u32>) {
+        frame_buffer.extend(values.iter().map(|color| color.as_u32()));
+    }
+}
+
+/// Escape values are colored with the palette of the app, after its transfer function.
+impl PixelValue for u8 {
+    fn color_frame(
+        values: &[Self],
+        palette: &Palette,
+        transfer: Transfer,
+        frame_buffer: &mut Vec<u32>,
+    ) {
+        let table = transfer.table(values);
+        frame_buffer.extend(
+            values
+                .iter()
+                .map(|&value| palette.color(table[value as usize]).as_u32()),
+        );
+    }
+}
+
+/// Escape values of a lit relief, colored like plain escape values and then shaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shaded {
+    pub value: u8,
+    pub shade: Shade,
+}
+
+impl PixelValue for Shaded {
+    fn color_frame(
+        values: &[Self],
+        palette: &Palette,
+        transfer: Transfer,
+        frame_buffer: &mut Vec<u32>,
+    ) {
+        let table = transfer.table(&values.iter().map(|v| v.value).collect::<Vec<_>>());
+        frame_buffer.extend(values.iter().map(|v| {
+            v.shade
+                .apply(palette.color(table[v.value as usize]))
+                .as_u32()
+        }));
+    }
+}
+
+/// Palette indices, or colors of their own for pixels that do not use the palette.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteOrRgb {
+    Palette(u8),
+    Rgb(Rgb),
+}
+
+impl PixelValue for PaletteOrRgb {
+    fn color_frame(
+        values: &[Self],
+        palette: &Palette,
+        transfer: Transfer,
+        frame_buffer: &mut Vec<u32>,
+    ) {
+        let indices = values
+            .iter()
+            .filter_map(|v| match v {
+                Self::Palette(index) => Some(*index),
+                Self::Rgb(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let table = transfer.table(&indices);
+        frame_buffer.extend(values.iter().map(|v| match v {
+            Self::Palette(index) => palette.color(table[*index as usize]).as_u32(),
+            Self::Rgb(color) => color.as_u32(),
+        }));
+    }
+}
+
+/// Computes the values of pixels for any SIMD lane count, like a render kernel,
+/// given the params controlled in the app.
+pub trait RuleKernel: Sync {
+    type Output: PixelValue;
+
+    /// Whether the kernel colors by the orbit trap, so that clicking moves the trap.
+    const USES_TRAP: bool = false;
+
+    fn compute<const N: usize>(
+        &self,
+        z: SimdComplex<N>,
+        params: RuleParams,
+    ) -> Array<Self::Output, N>;
+}
+
+/// Runs a rule kernel with the params of a frame.
+struct WithParams<'a, K> {
+    kernel: &'a K,
+    params: RuleParams,
+}
+
+impl<K: RuleKernel> Kernel for WithParams<'_, K> {
+    type Output = K::Output;
+
+    #[inline(always)]
+    fn compute<const N: usize>(&self, z: SimdComplex<N>) -> Array<Self::Output, N> {
+        self.kernel.compute(z, self.params)
+    }
+}
+
+pub struct FractalExplorerApp<K: RuleKernel> {
     window: Window,
     frame_renderer: Renderer,
     font_renderer: FbFontRenderer,
     frame_buffer: Vec<u32>,
     view: ComplexPlaneView,
-    color_computer: F,
-    seed: Complex,
+    kernel: K,
+    /// The values of the pixels of the last frame
+    pixels: Vec<K::Output>,
+    /// The palettes that can be cycled through, with their names
+    palettes: Vec<(String, Palette)>,
+    palette_index: usize,
+    /// How far the palette is rotated by color cycling, in palette colors
+    palette_offset: f64,
+    /// How many palette colors the palette is rotated by each frame while color cycling
+    cycling_speed: f64,
+    is_cycling: bool,
+    transfer: Transfer,
+    params: RuleParams,
+    render_time: Duration,
     should_render: bool,
+    /// Whether the pixels of the last frame need to be colored again, without rendering them
+    should_recolor: bool,
     display_stats: bool,
 }
 
-impl<F> FractalExplorerApp<F>
-where
-    F: FnSync(SimdComplex, Complex) -> Array<Rgb>,
-{
-    const INITIAL_SEED: Complex = Complex::new(-0.75, 0.2);
+impl<K: RuleKernel> FractalExplorerApp<K> {
+    const INITIAL_PARAMS: RuleParams = RuleParams {
+        seed: Complex::new(-0.75, 0.2),
+        exponent: Complex::new(2.0, 0.0),
+        root_finding: RootFindingParams::DEFAULT,
+        bailout: Bailout::DEFAULT,
+        trap: OrbitTrap::DEFAULT,
+        // Set from the view before each frame
+        pixel_size: 0.0,
+    };
     const BASE_SEED_STEP: f64 = 0.001;
+    /// Exponents are kept on a grid with this many steps per unit,
+    /// so that integer exponents (which are much faster) can be reached exactly.
+    const EXPONENT_STEPS_PER_UNIT: f64 = 100.0;
+    /// The relaxation factor is kept on a grid in the same way, so that 1 can be reached exactly.
+    const RELAXATION_STEPS_PER_UNIT: f64 = 100.0;
+    const TOLERANCE_RANGE: (f64, f64) = (1e-12, 1e-1);
+    /// Bailouts above this value wrap around to infinity (no bailout).
+    const MAX_FINITE_BAILOUT: f64 = 1e8;
+    const BAILOUT_RADIUS_RANGE: (f64, f64) = (0.5, 1e6);
+    const TRAP_SIZE_RANGE: (f64, f64) = (1e-3, 10.0);
+    const INITIAL_CYCLING_SPEED: f64 = 1.0;
+    /// Cycling speeds are kept on a grid with this many steps per palette color.
+    const CYCLING_SPEED_STEPS_PER_UNIT: f64 = 4.0;
+    const MAX_CYCLING_SPEED: f64 = 16.0;
     const FONT_COLOR: Rgb = Rgb(255, 255, 255);
     const TEXT_POS_X: usize = 20;
     const FRAMES_PER_SECOND: u32 = 60;
@@ -40,7 +194,7 @@ where
         .checked_div(Self::FRAMES_PER_SECOND)
         .expect("FPS should not be zero");
 
-    pub fn new(title: impl AsRef<str>, width: usize, height: usize, color_computer: F) -> Self {
+    pub fn new(title: impl AsRef<str>, width: usize, height: usize, kernel: K) -> Self {
         Self {
             window: Window::new(title.as_ref(), width, height, WindowOptions::default())
                 .unwrap_or_else(|e| panic!("{}", e)),
@@ -48,13 +202,34 @@ where
             font_renderer: font6x8::new_renderer(width, height, Self::FONT_COLOR.as_u32()),
             frame_buffer: vec![0u32; width * height],
             view: ComplexPlaneView::new(width, height),
-            color_computer,
-            seed: Self::INITIAL_SEED,
+            kernel,
+            pixels: Vec::with_capacity(width * height),
+            palettes: palettes::all()
+                .map(|(name, palette)| (name.to_string(), palette.clone()))
+                .to_vec(),
+            // Blue-green
+            palette_index: 1,
+            palette_offset: 0.0,
+            cycling_speed: Self::INITIAL_CYCLING_SPEED,
+            is_cycling: false,
+            transfer: Transfer::Linear,
+            params: Self::INITIAL_PARAMS,
+            render_time: Duration::ZERO,
             should_render: true,
+            should_recolor: false,
             display_stats: false,
         }
     }
 
+    /// Adds palettes after the built-in ones and starts with the first of them.
+    pub fn with_palettes(mut self, palettes: Vec<(String, Palette)>) -> Self {
+        if !palettes.is_empty() {
+            self.palette_index = self.palettes.len();
+            self.palettes.extend(palettes);
+        }
+        self
+    }
+
     pub fn main_loop(&mut self) {
         while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
             let start = Instant::now();
@@ -74,6 +249,12 @@ where
             self.update_view_scale(y);
         }
 
+        if K::USES_TRAP && self.window.get_mouse_down(MouseButton::Left) {
+            if let Some((x, y)) = self.window.get_mouse_pos(MouseMode::Discard) {
+                self.move_trap(x, y);
+            }
+        }
+
         self.window.get_keys().iter().for_each(|&k| match k {
             Key::W => self.translate_view(Direction::Up),
             Key::S => self.translate_view(Direction::Down),
@@ -83,6 +264,14 @@ where
             Key::Down => self.translate_seed(Direction::Down),
             Key::Left => self.translate_seed(Direction::Left),
             Key::Right => self.translate_seed(Direction::Right),
+            Key::Equal => self.translate_exponent(Direction::Right),
+            Key::Minus => self.translate_exponent(Direction::Left),
+            Key::RightBracket => self.translate_exponent(Direction::Up),
+            Key::LeftBracket => self.translate_exponent(Direction::Down),
+            Key::L => self.translate_relaxation(Direction::Right),
+            Key::J => self.translate_relaxation(Direction::Left),
+            Key::I => self.translate_relaxation(Direction::Up),
+            Key::K => self.translate_relaxation(Direction::Down),
             _ => (),
         });
 
@@ -92,11 +281,32 @@ where
             .for_each(|&k| match k {
                 Key::Q => self.toggle_stat_display(),
                 Key::R => self.reset(),
+                Key::T => self.scale_tolerance(10.0),
+                Key::G => self.scale_tolerance(0.1),
+                Key::Y => self.scale_bailout(10.0),
+                Key::H => self.scale_bailout(0.1),
+                Key::B => self.cycle_bailout_test(),
+                Key::Period => self.scale_bailout_radius(2.0),
+                Key::Comma => self.scale_bailout_radius(0.5),
+                Key::X => self.cycle_trap_shape(),
+                Key::V => self.scale_trap_size(2.0),
+                Key::C => self.scale_trap_size(0.5),
+                Key::E => self.cycle_transfer(),
+                Key::P => self.cycle_palette(),
+                Key::Z => self.toggle_color_cycling(),
+                Key::M => self.translate_cycling_speed(1.0),
+                Key::N => self.translate_cycling_speed(-1.0),
                 _ => (),
             });
 
+        if self.is_cycling {
+            self.advance_color_cycling();
+        }
+
         if self.should_render {
             self.render();
+        } else if self.should_recolor {
+            self.recolor();
         }
 
         self.window
@@ -119,37 +329,193 @@ where
     }
 
     fn translate_seed(&mut self, direction: Direction) {
-        self.seed += direction.as_complex() * Self::BASE_SEED_STEP * self.view.scale();
+        self.params.seed += direction.as_complex() * Self::BASE_SEED_STEP * self.view.scale();
         self.should_render = true;
     }
 
+    fn translate_exponent(&mut self, direction: Direction) {
+        let steps = self.params.exponent * Self::EXPONENT_STEPS_PER_UNIT + direction.as_complex();
+        self.params.exponent =
+            Complex::new(steps.re.round(), steps.im.round()) / Self::EXPONENT_STEPS_PER_UNIT;
+        self.should_render = true;
+    }
+
+    fn translate_relaxation(&mut self, direction: Direction) {
+        let relaxation = &mut self.params.root_finding.relaxation;
+        let steps = *relaxation * Self::RELAXATION_STEPS_PER_UNIT + direction.as_complex();
+        *relaxation =
+            Complex::new(steps.re.round(), steps.im.round()) / Self::RELAXATION_STEPS_PER_UNIT;
+        self.should_render = true;
+    }
+
+    fn scale_tolerance(&mut self, factor: f64) {
+        let (min, max) = Self::TOLERANCE_RANGE;
+        let tolerance = &mut self.params.root_finding.tolerance;
+        *tolerance = (*tolerance * factor).clamp(min, max);
+        self.should_render = true;
+    }
+
+    /// Scales the bailout radius, cycling through infinity past the largest finite value.
+    fn scale_bailout(&mut self, factor: f64) {
+        let bailout = &mut self.params.root_finding.bailout;
+        *bailout = if bailout.is_infinite() {
+            if factor < 1.0 {
+                Self::MAX_FINITE_BAILOUT
+            } else {
+                f64::INFINITY
+            }
+        } else if *bailout * factor > Self::MAX_FINITE_BAILOUT {
+            f64::INFINITY
+        } else {
+            (*bailout * factor).max(1.0)
+        };
+        self.should_render = true;
+    }
+
+    fn cycle_bailout_test(&mut self) {
+        let tests = BailoutTest::ALL;
+        let test = &mut self.params.bailout.test;
+        let index = tests.iter().position(|t| t == test).unwrap_or(0);
+        *test = tests[(index + 1) % tests.len()];
+        self.should_render = true;
+    }
+
+    fn scale_bailout_radius(&mut self, factor: f64) {
+        let (min, max) = Self::BAILOUT_RADIUS_RANGE;
+        let radius = &mut self.params.bailout.radius;
+        *radius = (*radius * factor).clamp(min, max);
+        self.should_render = true;
+    }
+
+    /// Moves the orbit trap to the point under the mouse cursor.
+    fn move_trap(&mut self, x: f32, y: f32) {
+        let center = self.view.pixel_mapper()(x as usize, y as usize);
+        if center != self.params.trap.center {
+            self.params.trap.center = center;
+            self.should_render = true;
+        }
+    }
+
+    fn cycle_trap_shape(&mut self) {
+        let shapes = TrapShape::ALL;
+        let shape = &mut self.params.trap.shape;
+        let index = shapes.iter().position(|s| s == shape).unwrap_or(0);
+        *shape = shapes[(index + 1) % shapes.len()];
+        self.should_render = true;
+    }
+
+    fn scale_trap_size(&mut self, factor: f64) {
+        let (min, max) = Self::TRAP_SIZE_RANGE;
+        let size = &mut self.params.trap.size;
+        *size = (*size * factor).clamp(min, max);
+        self.should_render = true;
+    }
+
+    /// Shows the stats, which tell that the palette controls are unavailable,
+    /// if the pixels are not colored with the palette. Returns whether they are.
+    fn check_palette_controls(&mut self) -> bool {
+        if !K::Output::USES_PALETTE {
+            self.display_stats = true;
+            self.should_recolor = true;
+        }
+        K::Output::USES_PALETTE
+    }
+
+    fn cycle_palette(&mut self) {
+        if !self.check_palette_controls() {
+            return;
+        }
+        self.palette_index = (self.palette_index + 1) % self.palettes.len();
+        self.should_recolor = true;
+    }
+
+    fn toggle_color_cycling(&mut self) {
+        if !self.check_palette_controls() {
+            return;
+        }
+        self.is_cycling = !self.is_cycling;
+        self.should_recolor = true;
+    }
+
+    /// Changes the cycling speed by a number of steps. Negative speeds cycle the other way.
+    fn translate_cycling_speed(&mut self, steps: f64) {
+        if !self.check_palette_controls() {
+            return;
+        }
+        let steps = (self.cycling_speed * Self::CYCLING_SPEED_STEPS_PER_UNIT + steps).round();
+        self.cycling_speed = (steps / Self::CYCLING_SPEED_STEPS_PER_UNIT)
+            .clamp(-Self::MAX_CYCLING_SPEED, Self::MAX_CYCLING_SPEED);
+        self.should_recolor = true;
+    }
+
+    fn advance_color_cycling(&mut self) {
+        self.palette_offset =
+            (self.palette_offset + self.cycling_speed).rem_euclid(MAX_ITERS as f64);
+        self.should_recolor = true;
+    }
+
+    fn cycle_transfer(&mut self) {
+        if !self.check_palette_controls() {
+            return;
+        }
+        let transfers = Transfer::ALL;
+        let index = transfers
+            .iter()
+            .position(|&t| t == self.transfer)
+            .unwrap_or(0);
+        self.transfer = transfers[(index + 1) % transfers.len()];
+        self.should_recolor = true;
+    }
+
     fn toggle_stat_display(&mut self) {
         self.display_stats = !self.display_stats;
-        self.should_render = true;
+        self.should_recolor = true;
     }
 
     fn reset(&mut self) {
         self.view.reset();
-        self.seed = Self::INITIAL_SEED;
+        self.params = Self::INITIAL_PARAMS;
+        self.palette_offset = 0.0;
+        self.cycling_speed = Self::INITIAL_CYCLING_SPEED;
+        self.is_cycling = false;
         self.should_render = true;
     }
 
     fn render(&mut self) {
         let start = Instant::now();
-        let pixels = self.frame_renderer.render(&self.view, {
-            let seed = self.seed;
-            let color_computer = &self.color_computer;
-            move |z| color_computer(z, seed)
-        });
+        let kernel = WithParams {
+            kernel: &self.kernel,
+            params: RuleParams {
+                pixel_size: self.view.pixel_size(),
+                ..self.params
+            },
+        };
+        let pixels = self.frame_renderer.render(&self.view, &kernel);
+        self.pixels.clear();
+        self.pixels.extend(pixels);
+        self.render_time = start.elapsed();
+        self.should_render = false;
+        self.recolor();
+    }
+
+    /// Colors the pixels of the last frame, with the palette rotated by color cycling.
+    fn recolor(&mut self) {
+        let (_, palette) = &self.palettes[self.palette_index];
         self.frame_buffer.clear();
-        self.frame_buffer.extend(pixels.map(Rgb::as_u32));
+        K::Output::color_frame(
+            &self.pixels,
+            &palette.rotated(self.palette_offset as u8),
+            self.transfer,
+            &mut self.frame_buffer,
+        );
         if self.display_stats {
-            self.render_stats(start.elapsed());
+            self.render_stats();
         }
-        self.should_render = false;
+        self.should_recolor = false;
     }
 
-    fn render_stats(&mut self, render_time: Duration) {
+    fn render_stats(&mut self) {
+        let render_time = self.render_time;
         self.render_text(
             20,
             &format!(
@@ -160,7 +526,53 @@ where
         );
         self.render_text(40, &format!("Scale = {:+e}", self.view.scale()));
         self.render_text(60, &format!("Offset = {:.5}", self.view.offset()));
-        self.render_text(80, &format!("Seed = {:.5}", self.seed));
+        self.render_text(80, &format!("Seed = {:.5}", self.params.seed));
+        self.render_text(
+            100,
+            &format!(
+                "Simd = {} ({} lanes)",
+                self.frame_renderer.simd_level(),
+                self.frame_renderer.lanes()
+            ),
+        );
+        self.render_text(120, &format!("Exponent = {:.2}", self.params.exponent));
+        let root_finding = self.params.root_finding;
+        self.render_text(140, &format!("Relaxation = {:.2}", root_finding.relaxation));
+        self.render_text(160, &format!("Tolerance = {:e}", root_finding.tolerance));
+        self.render_text(180, &format!("Bailout = {:e}", root_finding.bailout));
+        let bailout = self.params.bailout;
+        self.render_text(
+            200,
+            &format!("Escape = {} < {}", bailout.test.name(), bailout.radius),
+        );
+        let trap = self.params.trap;
+        self.render_text(
+            220,
+            &format!(
+                "Trap = {} at {:.5} (size {})",
+                trap.shape.name(),
+                trap.center,
+                trap.size
+            ),
+        );
+        if !K::Output::USES_PALETTE {
+            self.render_text(
+                240,
+                "Palette = unavailable, this mode has colors of its own",
+            );
+            return;
+        }
+        self.render_text(240, &format!("Transfer = {}", self.transfer.name()));
+        let (palette_name, _) = &self.palettes[self.palette_index];
+        self.render_text(260, &format!("Palette = {}", palette_name));
+        self.render_text(
+            280,
+            &format!(
+                "Cycling = {} ({:+} colors per frame)",
+                if self.is_cycling { "on" } else { "off" },
+                self.cycling_speed
+            ),
+        );
     }
 
     fn render_text(&mut self, pos_y: usize, text: &str) {
@@ -0,0 +1,377 @@
+//! Forward-mode automatic differentiation over `SimdComplex` lanes.
+//!
+//! A `Jet` is a Taylor series truncated after `TERMS` coefficients. Evaluating a function
+//! on `Jet::variable(z)` yields its value and its first `TERMS - 1` derivatives at `z`,
+//! so rules only need `f` and never a hand-written derivative.
+//! `Dual` (value and first derivative) is the special case used by Newton's method.
+
+#![allow(dead_code)]
+
+use crate::simd::{SimdComplex, SIMD_LEN};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Truncated Taylor series in one complex variable, evaluated on every lane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Jet<const N: usize = SIMD_LEN, const TERMS: usize = 2> {
+    /// `coefficients[k]` is the k-th derivative divided by `k!`.
+    coefficients: [SimdComplex<N>; TERMS],
+}
+
+/// Dual number: value and first derivative.
+pub type Dual<const N: usize = SIMD_LEN> = Jet<N, 2>;
+
+impl<const N: usize, const TERMS: usize> Jet<N, TERMS> {
+    pub fn constant(value: SimdComplex<N>) -> Self {
+        let mut coefficients = [SimdComplex::default(); TERMS];
+        coefficients[0] = value;
+        Self { coefficients }
+    }
+
+    /// The independent variable, whose derivative is 1.
+    pub fn variable(value: SimdComplex<N>) -> Self {
+        let mut jet = Self::constant(value);
+        if TERMS > 1 {
+            jet.coefficients[1] = SimdComplex::splat(1.0, 0.0);
+        }
+        jet
+    }
+
+    pub fn value(&self) -> SimdComplex<N> {
+        self.coefficients[0]
+    }
+
+    /// Returns the derivative of the given order, with order 0 being the value.
+    pub fn derivative(&self, order: usize) -> SimdComplex<N> {
+        let factorial: f64 = (1..=order).map(|k| k as f64).product();
+        self.coefficients[order] * factorial
+    }
+
+    /// Returns the k-th Taylor coefficient, which is the k-th derivative divided by `k!`.
+    pub fn coefficient(&self, k: usize) -> SimdComplex<N> {
+        self.coefficients[k]
+    }
+
+    pub fn recip(self) -> Self {
+        Self::constant(SimdComplex::splat(1.0, 0.0)) / self
+    }
+
+    /// Returns the value followed by all available derivatives.
+    pub fn derivatives(&self) -> [SimdComplex<N>; TERMS] {
+        std::array::from_fn(|k| self.derivative(k))
+    }
+
+    pub fn powi(self, exp: u32) -> Self {
+        // Same repeated squaring as `SimdComplex::powi`, so that values match exactly
+        if exp == 0 {
+            return Self::constant(SimdComplex::splat(1.0, 0.0));
+        }
+        let mut base = self;
+        let mut exp = exp;
+        while exp & 1 == 0 {
+            base = base * base;
+            exp >>= 1;
+        }
+        let mut res = base;
+        exp >>= 1;
+        while exp > 0 {
+            base = base * base;
+            if exp & 1 == 1 {
+                res = res * base;
+            }
+            exp >>= 1;
+        }
+        res
+    }
+
+    pub fn exp(self) -> Self {
+        // (e^a)' = e^a * a'
+        let mut res = [SimdComplex::default(); TERMS];
+        res[0] = self.value().exp();
+        for k in 1..TERMS {
+            res[k] = self.weighted_sum(&res, k) / k as f64;
+        }
+        Self { coefficients: res }
+    }
+
+    /// Natural logarithm, using the principal branch.
+    pub fn ln(self) -> Self {
+        // a * (ln a)' = a'
+        let a = &self.coefficients;
+        let mut res = [SimdComplex::default(); TERMS];
+        res[0] = a[0].ln();
+        for k in 1..TERMS {
+            let mut sum = a[k] * k as f64;
+            for j in 1..k {
+                sum = sum - res[j] * a[k - j] * j as f64;
+            }
+            res[k] = sum / a[0] / k as f64;
+        }
+        Self { coefficients: res }
+    }
+
+    pub fn sin(self) -> Self {
+        self.sin_cos().0
+    }
+
+    pub fn cos(self) -> Self {
+        self.sin_cos().1
+    }
+
+    pub fn sinh(self) -> Self {
+        self.sinh_cosh().0
+    }
+
+    pub fn cosh(self) -> Self {
+        self.sinh_cosh().1
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        // (sin a)' = cos a * a', (cos a)' = -sin a * a'
+        let mut sin = [SimdComplex::default(); TERMS];
+        let mut cos = [SimdComplex::default(); TERMS];
+        sin[0] = self.value().sin();
+        cos[0] = self.value().cos();
+        for k in 1..TERMS {
+            sin[k] = self.weighted_sum(&cos, k) / k as f64;
+            cos[k] = -self.weighted_sum(&sin, k) / k as f64;
+        }
+        (Self { coefficients: sin }, Self { coefficients: cos })
+    }
+
+    fn sinh_cosh(self) -> (Self, Self) {
+        // (sinh a)' = cosh a * a', (cosh a)' = sinh a * a'
+        let mut sinh = [SimdComplex::default(); TERMS];
+        let mut cosh = [SimdComplex::default(); TERMS];
+        sinh[0] = self.value().sinh();
+        cosh[0] = self.value().cosh();
+        for k in 1..TERMS {
+            sinh[k] = self.weighted_sum(&cosh, k) / k as f64;
+            cosh[k] = self.weighted_sum(&sinh, k) / k as f64;
+        }
+        (Self { coefficients: sinh }, Self { coefficients: cosh })
+    }
+
+    /// Returns the sum of `j * a[j] * b[k - j]` for `j` in `1..=k`. If `g' = h` and `b` holds
+    /// the coefficients of `h(a)`, this is `k` times the k-th coefficient of `g(a)`.
+    fn weighted_sum(&self, b: &[SimdComplex<N>; TERMS], k: usize) -> SimdComplex<N> {
+        (1..=k).fold(SimdComplex::default(), |sum, j| {
+            sum + self.coefficients[j] * b[k - j] * j as f64
+        })
+    }
+}
+
+/// Evaluates `f` and its first `TERMS - 1` derivatives at `z`.
+#[inline(always)]
+pub fn derivatives<const N: usize, const TERMS: usize>(
+    f: impl Fn(Jet<N, TERMS>) -> Jet<N, TERMS>,
+    z: SimdComplex<N>,
+) -> [SimdComplex<N>; TERMS] {
+    f(Jet::variable(z)).derivatives()
+}
+
+impl<const N: usize, const TERMS: usize> Neg for Jet<N, TERMS> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            coefficients: self.coefficients.map(Neg::neg),
+        }
+    }
+}
+
+impl<const N: usize, const TERMS: usize> Add for Jet<N, TERMS> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            coefficients: std::array::from_fn(|k| self.coefficients[k] + rhs.coefficients[k]),
+        }
+    }
+}
+
+impl<const N: usize, const TERMS: usize> Sub for Jet<N, TERMS> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            coefficients: std::array::from_fn(|k| self.coefficients[k] - rhs.coefficients[k]),
+        }
+    }
+}
+
+impl<const N: usize, const TERMS: usize> Mul for Jet<N, TERMS> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (&self.coefficients, &rhs.coefficients);
+        Self {
+            coefficients: std::array::from_fn(|k| {
+                (1..=k).fold(a[0] * b[k], |sum, j| sum + a[j] * b[k - j])
+            }),
+        }
+    }
+}
+
+impl<const N: usize, const TERMS: usize> Div for Jet<N, TERMS> {
+    type Output = Self;
+
+    // Series division needs the other operators
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Self) -> Self::Output {
+        let (a, b) = (&self.coefficients, &rhs.coefficients);
+        let mut res = [SimdComplex::default(); TERMS];
+        for k in 0..TERMS {
+            let sum = (1..=k).fold(a[k], |sum, j| sum - b[j] * res[k - j]);
+            res[k] = sum / b[0];
+        }
+        Self { coefficients: res }
+    }
+}
+
+/// Arithmetic with constants only touches the coefficients it affects,
+/// so values are computed exactly as they would be on plain `SimdComplex` numbers.
+macro_rules! impl_constant_ops {
+    ($constant:ty) => {
+        impl<const N: usize, const TERMS: usize> Add<$constant> for Jet<N, TERMS> {
+            type Output = Self;
+
+            fn add(mut self, rhs: $constant) -> Self::Output {
+                self.coefficients[0] = self.coefficients[0] + rhs;
+                self
+            }
+        }
+
+        impl<const N: usize, const TERMS: usize> Sub<$constant> for Jet<N, TERMS> {
+            type Output = Self;
+
+            fn sub(mut self, rhs: $constant) -> Self::Output {
+                self.coefficients[0] = self.coefficients[0] - rhs;
+                self
+            }
+        }
+
+        impl<const N: usize, const TERMS: usize> Mul<$constant> for Jet<N, TERMS> {
+            type Output = Self;
+
+            fn mul(self, rhs: $constant) -> Self::Output {
+                Self {
+                    coefficients: self.coefficients.map(|a| a * rhs),
+                }
+            }
+        }
+
+        impl<const N: usize, const TERMS: usize> Div<$constant> for Jet<N, TERMS> {
+            type Output = Self;
+
+            fn div(self, rhs: $constant) -> Self::Output {
+                Self {
+                    coefficients: self.coefficients.map(|a| a / rhs),
+                }
+            }
+        }
+    };
+}
+
+impl_constant_ops!(f64);
+impl_constant_ops!(SimdComplex<N>);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        autodiff::{derivatives, Dual, Jet},
+        simd::SimdComplex,
+        utils::Complex,
+    };
+    use nalgebra::ComplexField;
+
+    const TOLERANCE: f64 = 1e-12;
+    const POINTS: [Complex; 4] = [
+        Complex::new(0.5, -1.25),
+        Complex::new(-1.5, 0.75),
+        Complex::new(2.0, 0.0),
+        Complex::new(0.25, 1.5),
+    ];
+
+    #[test]
+    fn polynomial_derivatives_work() {
+        let [f, df, d2f, d3f, d4f] =
+            derivatives(|z: Jet<4, 5>| z.powi(3) * 2.0 - z * z + 5.0, points());
+        check(f, |z| z.powi(3) * 2.0 - z * z + 5.0);
+        check(df, |z| z * z * 6.0 - z * 2.0);
+        check(d2f, |z| z * 12.0 - 2.0);
+        check(d3f, |_| Complex::new(12.0, 0.0));
+        check(d4f, |_| Complex::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn quotient_derivatives_work() {
+        let [f, df, d2f] = derivatives(|z: Jet<4, 3>| (z + 1.0) / (z * z - 3.0), points());
+        check(f, |z| (z + 1.0) / (z * z - 3.0));
+        check(df, |z| -(z * z + z * 2.0 + 3.0) / (z * z - 3.0).powi(2));
+        check(d2f, |z| {
+            (z.powi(3) + z * z * 3.0 + z * 9.0 + 3.0) * 2.0 / (z * z - 3.0).powi(3)
+        });
+    }
+
+    #[test]
+    fn transcendental_derivatives_work() {
+        let [f, df, d2f] = derivatives(|z: Jet<4, 3>| z.exp(), points());
+        check(f, |z| z.exp());
+        check(df, |z| z.exp());
+        check(d2f, |z| z.exp());
+
+        let [f, df, d2f] = derivatives(|z: Jet<4, 3>| z.ln(), points());
+        check(f, |z| z.ln());
+        check(df, |z| z.recip());
+        check(d2f, |z| -(z * z).recip());
+
+        let [f, df, d2f, d3f] = derivatives(|z: Jet<4, 4>| z.sin() * z.cosh(), points());
+        check(f, |z| z.sin() * z.cosh());
+        check(df, |z| z.cos() * z.cosh() + z.sin() * z.sinh());
+        check(d2f, |z| z.cos() * z.sinh() * 2.0);
+        check(d3f, |z| (z.cos() * z.cosh() - z.sin() * z.sinh()) * 2.0);
+
+        let [f, df] = derivatives(|z: Dual<4>| (z * 2.0).cos() - z.sinh(), points());
+        check(f, |z| (z * 2.0).cos() - z.sinh());
+        check(df, |z| -(z * 2.0).sin() * 2.0 - z.cosh());
+    }
+
+    #[test]
+    fn reciprocal_coefficients_work() {
+        let g = Jet::<4, 4>::variable(points()).recip();
+        for k in 0..4 {
+            // 1/z = sum((-1)^k (z0 - z)^k ... ), so the k-th coefficient is (-1)^k / z0^(k+1)
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            check(g.coefficient(k), |z| z.powi(k as i32 + 1).recip() * sign);
+        }
+    }
+
+    #[test]
+    fn values_match_plain_arithmetic() {
+        let z = points::<4>();
+        let c = SimdComplex::splat(-0.5, 0.25);
+        let jet = Jet::<4, 3>::variable(z).powi(5) * 2.0 - 1.0 + c;
+        assert_eq!(jet.value(), z.powi(5) * 2.0 - 1.0 + c);
+    }
+
+    fn check(actual: SimdComplex<4>, expected: impl Fn(Complex) -> Complex) {
+        for (i, z) in POINTS.into_iter().enumerate() {
+            let actual = Complex::new(actual.re[i], actual.im[i]);
+            let expected = expected(z);
+            assert!(
+                (actual - expected).modulus() <= TOLERANCE * expected.modulus().max(1.0),
+                "z = {}: {} != {}",
+                z,
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn points<const N: usize>() -> SimdComplex<N> {
+        SimdComplex {
+            re: std::array::from_fn(|i| POINTS[i % POINTS.len()].re).into(),
+            im: std::array::from_fn(|i| POINTS[i % POINTS.len()].im).into(),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Benchmark suite for the rules and the renderer.
+//!
+//! Every rule is rendered for each combination of view size, view depth, chunk count
+//! and dispatch: through a kernel at each supported SIMD level, as the app renders,
+//! and with fixed SIMD lane counts. Results are reported as JSON so that runs on
+//! different commits can be compared.
+
+use crate::{
+    color::{palettes, Rgb},
+    cpu::SimdLevel,
+    render::{Kernel, Renderer},
+    rules::{self, Bailout, RootFindingParams},
+    simd::{Array, SimdComplex},
+    utils::Complex,
+    view::ComplexPlaneView,
+};
+use itertools::Itertools;
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const SIZES: [(usize, usize); 2] = [(320, 180), (1280, 720)];
+const SCALES: [f64; 3] = [1.0, 1e-5, 1e-10];
+const LANE_COUNTS: [usize; 4] = [2, 4, 8, 16];
+/// Chunk counts as multiples of the renderer's default (one chunk per thread).
+/// Zero stands for a single chunk.
+const CHUNK_COUNT_MULTIPLIERS: [usize; 3] = [0, 1, 4];
+const MIN_FRAMES: u32 = 3;
+const MIN_DURATION: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BenchRule {
+    Julia,
+    Mandelbrot,
+    Nova,
+    Newton,
+}
+
+impl BenchRule {
+    const ALL: [BenchRule; 4] = [
+        BenchRule::Julia,
+        BenchRule::Mandelbrot,
+        BenchRule::Nova,
+        BenchRule::Newton,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            BenchRule::Julia => "julia",
+            BenchRule::Mandelbrot => "mandelbrot",
+            BenchRule::Nova => "nova",
+            BenchRule::Newton => "newton",
+        }
+    }
+
+    /// Point close to the boundary of the fractal, so that deep views still contain detail.
+    fn center(self) -> Complex {
+        match self {
+            BenchRule::Julia => Complex::new(-0.4027437757112985, 0.03337908553155888),
+            BenchRule::Mandelbrot => Complex::new(-0.7436438870371587, 0.1318259042053119),
+            BenchRule::Nova => Complex::new(-0.33180066245801726, -0.1407069598070452),
+            // Real preimage of the pole at 0
+            BenchRule::Newton => Complex::new(-0.7937005259840998, 0.0),
+        }
+    }
+
+    fn apply<const N: usize>(self, z: SimdComplex<N>) -> Array<u8, N> {
+        match self {
+            BenchRule::Julia => rules::julia(z, SimdComplex::splat(-0.75, 0.2), Bailout::DEFAULT),
+            BenchRule::Mandelbrot => rules::mandelbrot(z, Bailout::DEFAULT),
+            BenchRule::Nova => rules::nova(
+                SimdComplex::splat(1.0, 0.0),
+                z,
+                RootFindingParams::DEFAULT,
+                |z| z * z * z - 1.0,
+                |z| z * z * 3.0,
+            ),
+            BenchRule::Newton => rules::newton(z, |z| z * z * z - 1.0, |z| z * z * 3.0),
+        }
+    }
+}
+
+/// Renders the escape or convergence counts of a rule.
+struct CountKernel(BenchRule);
+
+impl Kernel for CountKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>) -> Array<u8, N> {
+        self.0.apply(z)
+    }
+}
+
+/// Renders a rule colored with a palette, like the app does.
+struct ColorKernel(BenchRule);
+
+impl Kernel for ColorKernel {
+    type Output = Rgb;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>) -> Array<Rgb, N> {
+        palettes::BLUE_GREEN.color_array(self.0.apply(z))
+    }
+}
+
+/// How pixels are handed to the rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dispatch {
+    /// `Renderer::render` with a kernel, at the lane count of the SIMD level.
+    Kernel,
+    /// `Renderer::render_with_lanes` with a fixed lane count.
+    FixedLanes,
+}
+
+impl Dispatch {
+    fn name(self) -> &'static str {
+        match self {
+            Dispatch::Kernel => "kernel",
+            Dispatch::FixedLanes => "fixed_lanes",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BenchCase {
+    rule: BenchRule,
+    width: usize,
+    height: usize,
+    scale: f64,
+    chunk_count: usize,
+    dispatch: Dispatch,
+    simd_level: SimdLevel,
+    lanes: usize,
+}
+
+#[derive(Debug, Clone)]
+struct BenchResult {
+    case: BenchCase,
+    frames: u32,
+    frame_time: Duration,
+    iterations_per_frame: u64,
+}
+
+impl BenchResult {
+    fn pixels_per_second(&self) -> f64 {
+        (self.case.width * self.case.height) as f64 / self.frame_time.as_secs_f64()
+    }
+
+    fn iterations_per_second(&self) -> f64 {
+        self.iterations_per_frame as f64 / self.frame_time.as_secs_f64()
+    }
+}
+
+/// Runs all benchmarks, printing progress to stderr.
+/// The JSON report is written to `output` if given, otherwise to stdout.
+pub fn run(output: Option<&Path>) -> io::Result<()> {
+    let default_chunk_count = Renderer::new().chunk_count();
+    let cases = all_cases(default_chunk_count);
+    let mut results = Vec::with_capacity(cases.len());
+    for (i, case) in cases.into_iter().enumerate() {
+        let result = run_case(case);
+        eprintln!(
+            "[{:3}] {:<10} {:>4}x{:<4} scale={:<6e} chunks={:<3} {:<11} level={:<7} lanes={:<2} {:8.3} ms/frame {:10.3e} px/s {:10.3e} it/s",
+            i,
+            result.case.rule.name(),
+            result.case.width,
+            result.case.height,
+            result.case.scale,
+            result.case.chunk_count,
+            result.case.dispatch.name(),
+            result.case.simd_level.to_string(),
+            result.case.lanes,
+            result.frame_time.as_secs_f64() * 1000.0,
+            result.pixels_per_second(),
+            result.iterations_per_second(),
+        );
+        results.push(result);
+    }
+
+    let report = to_json(&results);
+    match output {
+        Some(path) => fs::write(path, report),
+        None => {
+            println!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+fn all_cases(default_chunk_count: usize) -> Vec<BenchCase> {
+    let chunk_counts = CHUNK_COUNT_MULTIPLIERS
+        .map(|multiplier| (default_chunk_count * multiplier).max(1))
+        .into_iter()
+        .dedup()
+        .collect_vec();
+    let mut cases = Vec::new();
+    for rule in BenchRule::ALL {
+        for (width, height) in SIZES {
+            for scale in SCALES {
+                for &chunk_count in &chunk_counts {
+                    let case = |dispatch, simd_level, lanes| BenchCase {
+                        rule,
+                        width,
+                        height,
+                        scale,
+                        chunk_count,
+                        dispatch,
+                        simd_level,
+                        lanes,
+                    };
+                    for simd_level in SimdLevel::supported() {
+                        cases.push(case(
+                            Dispatch::Kernel,
+                            simd_level,
+                            simd_level.register_lanes(),
+                        ));
+                    }
+                    for lanes in LANE_COUNTS {
+                        cases.push(case(Dispatch::FixedLanes, SimdLevel::detect(), lanes));
+                    }
+                }
+            }
+        }
+    }
+    cases
+}
+
+fn run_case(case: BenchCase) -> BenchResult {
+    match (case.dispatch, case.lanes) {
+        (Dispatch::Kernel, _) => run_case_with_kernel(case),
+        (Dispatch::FixedLanes, 2) => run_case_with_lanes::<2>(case),
+        (Dispatch::FixedLanes, 4) => run_case_with_lanes::<4>(case),
+        (Dispatch::FixedLanes, 8) => run_case_with_lanes::<8>(case),
+        (Dispatch::FixedLanes, 16) => run_case_with_lanes::<16>(case),
+        (Dispatch::FixedLanes, lanes) => panic!("Unsupported lane count: {}", lanes),
+    }
+}
+
+fn run_case_with_kernel(case: BenchCase) -> BenchResult {
+    let renderer = case_renderer(&case);
+    let view = case_view(&case);
+
+    // Untimed pass to count the iterations of each pixel
+    let iterations_per_frame = renderer
+        .render(&view, &CountKernel(case.rule))
+        .map(u64::from)
+        .sum();
+    let kernel = ColorKernel(case.rule);
+    time_frames(case, iterations_per_frame, || {
+        renderer.render(&view, &kernel)
+    })
+}
+
+fn run_case_with_lanes<const N: usize>(case: BenchCase) -> BenchResult {
+    let renderer = case_renderer(&case);
+    let view = case_view(&case);
+    let rule = case.rule;
+
+    // Untimed pass to count the iterations of each pixel
+    let iterations_per_frame = renderer
+        .render_with_lanes(&view, |z: SimdComplex<N>| rule.apply(z))
+        .map(u64::from)
+        .sum();
+    time_frames(case, iterations_per_frame, || {
+        renderer.render_with_lanes(&view, |z: SimdComplex<N>| {
+            palettes::BLUE_GREEN.color_array(rule.apply(z))
+        })
+    })
+}
+
+fn case_renderer(case: &BenchCase) -> Renderer {
+    Renderer::with_simd_level(case.simd_level)
+        .expect("Benchmarked SIMD levels should be supported by this CPU")
+        .with_chunk_count(case.chunk_count)
+}
+
+fn case_view(case: &BenchCase) -> ComplexPlaneView {
+    ComplexPlaneView::with_offset_and_scale(case.width, case.height, case.rule.center(), case.scale)
+}
+
+/// Renders frames with `render_frame` until both the minimum frame count and duration are reached.
+fn time_frames<I: Iterator<Item = Rgb>>(
+    case: BenchCase,
+    iterations_per_frame: u64,
+    render_frame: impl Fn() -> I,
+) -> BenchResult {
+    let mut frame_buffer = Vec::with_capacity(case.width * case.height);
+    let mut frames = 0;
+    let start = Instant::now();
+    while frames < MIN_FRAMES || start.elapsed() < MIN_DURATION {
+        frame_buffer.clear();
+        frame_buffer.extend(render_frame().map(Rgb::as_u32));
+        frames += 1;
+    }
+
+    BenchResult {
+        case,
+        frames,
+        frame_time: start.elapsed() / frames,
+        iterations_per_frame,
+    }
+}
+
+fn to_json(results: &[BenchResult]) -> String {
+    let mut json = String::new();
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"simd_level\": \"{}\",", SimdLevel::detect()).unwrap();
+    writeln!(json, "  \"threads\": {},", rayon::current_num_threads()).unwrap();
+    writeln!(json, "  \"results\": [").unwrap();
+    for (i, result) in results.iter().enumerate() {
+        let separator = if i + 1 < results.len() { "," } else { "" };
+        writeln!(
+            json,
+            "    {{\"rule\": \"{}\", \"width\": {}, \"height\": {}, \"scale\": {:e}, \"chunk_count\": {}, \"dispatch\": \"{}\", \"simd_level\": \"{}\", \"lanes\": {}, \"frames\": {}, \"frame_time_ms\": {:.6}, \"pixels_per_second\": {:.1}, \"iterations_per_second\": {:.1}}}{}",
+            result.case.rule.name(),
+            result.case.width,
+            result.case.height,
+            result.case.scale,
+            result.case.chunk_count,
+            result.case.dispatch.name(),
+            result.case.simd_level,
+            result.case.lanes,
+            result.frames,
+            result.frame_time.as_secs_f64() * 1000.0,
+            result.pixels_per_second(),
+            result.iterations_per_second(),
+            separator
+        )
+        .unwrap();
+    }
+    writeln!(json, "  ]").unwrap();
+    write!(json, "}}").unwrap();
+    json
+}
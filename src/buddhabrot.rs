@@ -0,0 +1,343 @@
+//! Density rendering of Mandelbrot orbits (Buddhabrot, Nebulabrot and anti-Buddhabrot).
+//!
+//! Instead of coloring each pixel by its own escape time, random points `c` are sampled
+//! and the orbits of `z = z^2 + c` are traced, counting how many times each pixel is visited.
+//! The visit counts are then tone-mapped to colors.
+
+use crate::{
+    color::Rgb,
+    simd::{Array, SimdComplex, SimdCounter, SimdDouble, SIMD_LEN},
+    utils::Complex,
+    view::ComplexPlaneView,
+};
+use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::simd::cmp::SimdPartialOrd;
+
+/// Visit counts of the red, green and blue channels of each pixel, row by row.
+pub type Histogram = Vec<[u32; 3]>;
+
+/// Which orbits contribute to the histogram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrbitSelection {
+    /// Orbits that escape before the iteration limit (Buddhabrot).
+    Escaping,
+    /// Orbits that do not escape before the iteration limit (anti-Buddhabrot).
+    Bounded,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuddhabrotRenderer {
+    /// Iteration limits of the red, green and blue channels.
+    iteration_limits: [u32; 3],
+    orbits: OrbitSelection,
+    samples: usize,
+    seed: u64,
+}
+
+impl BuddhabrotRenderer {
+    const DEFAULT_SAMPLES: usize = 10_000_000;
+    /// Samples are drawn in batches with their own random generator, so that the result
+    /// does not depend on how batches are scheduled on threads.
+    const BATCH_SIZE: usize = 1 << 14;
+    /// Every `c` outside of this radius escapes immediately.
+    const SAMPLE_RADIUS: f64 = 2.0;
+    const ESCAPE_RADIUS_SQUARED: f64 = 4.0;
+    /// Fraction of visited pixels that are allowed to saturate, so that a few very bright
+    /// pixels do not darken the rest of the image.
+    const SATURATED_FRACTION: f64 = 0.001;
+
+    pub fn buddhabrot(max_iters: u32) -> Self {
+        Self::nebulabrot([max_iters; 3])
+    }
+
+    /// Uses a different iteration limit for each color channel.
+    pub fn nebulabrot(iteration_limits: [u32; 3]) -> Self {
+        assert!(
+            iteration_limits.iter().all(|&limit| limit > 0),
+            "Iteration limits must be positive"
+        );
+        Self {
+            iteration_limits,
+            orbits: OrbitSelection::Escaping,
+            samples: Self::DEFAULT_SAMPLES,
+            seed: 0,
+        }
+    }
+
+    pub fn anti_buddhabrot(max_iters: u32) -> Self {
+        Self {
+            orbits: OrbitSelection::Bounded,
+            ..Self::buddhabrot(max_iters)
+        }
+    }
+
+    /// Overrides the number of sampled orbits.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Overrides the seed of the random generator, renders with the same seed are identical.
+    #[allow(dead_code)]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn render(&self, view: &ComplexPlaneView) -> Vec<Rgb> {
+        tone_map(&self.histogram(view))
+    }
+
+    /// Traces the sampled orbits in parallel and accumulates their visits in the view.
+    pub fn histogram(&self, view: &ComplexPlaneView) -> Histogram {
+        let pixel_count = view.width() * view.height();
+        (0..self.samples.div_ceil(Self::BATCH_SIZE))
+            .into_par_iter()
+            .fold(
+                || vec![[0; 3]; pixel_count],
+                |mut histogram, batch| {
+                    let batch_start = batch * Self::BATCH_SIZE;
+                    let batch_size = Self::BATCH_SIZE.min(self.samples - batch_start);
+                    self.trace_batch(batch, batch_size, view, &mut histogram);
+                    histogram
+                },
+            )
+            .reduce_with(|mut lhs, rhs| {
+                lhs.iter_mut().zip(rhs).for_each(|(l, r)| {
+                    l.iter_mut().zip(r).for_each(|(l, r)| *l += r);
+                });
+                lhs
+            })
+            .unwrap_or_else(|| vec![[0; 3]; pixel_count])
+    }
+
+    fn trace_batch(
+        &self,
+        batch: usize,
+        batch_size: usize,
+        view: &ComplexPlaneView,
+        histogram: &mut Histogram,
+    ) {
+        let mut rng =
+            fastrand::Rng::with_seed(self.seed ^ (batch as u64).wrapping_mul(0x9e37_79b9));
+        let max_iters = self.iteration_limits.into_iter().max().unwrap_or(0);
+        let locate_pixel = view.pixel_locator();
+        let width = view.width();
+        let samples = (0..batch_size).map(|_| self.sample(&mut rng)).collect_vec();
+        for chunk in samples.chunks(SIMD_LEN) {
+            let mut cs = SimdComplex::<SIMD_LEN>::default();
+            for (i, c) in chunk.iter().enumerate() {
+                cs.re[i] = c.re;
+                cs.im[i] = c.im;
+            }
+            let counts = escape_counts(cs, max_iters);
+            for (&c, &count) in chunk.iter().zip(&counts) {
+                trace_orbit(c, self.orbit_lengths(count), |z, visits| {
+                    if let Some((x, y)) = locate_pixel(z) {
+                        let counts = &mut histogram[y * width + x];
+                        counts.iter_mut().zip(visits).for_each(|(c, v)| *c += v);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Draws a random `c` in the sampling disk.
+    fn sample(&self, rng: &mut fastrand::Rng) -> Complex {
+        loop {
+            let mut coordinate = || (rng.f64() * 2.0 - 1.0) * Self::SAMPLE_RADIUS;
+            let c = Complex::new(coordinate(), coordinate());
+            let in_disk = c.norm_sqr() <= Self::SAMPLE_RADIUS * Self::SAMPLE_RADIUS;
+            // Points of the main cardioid and period-2 bulb never escape
+            if in_disk && (self.orbits == OrbitSelection::Bounded || !in_main_bulbs(c)) {
+                return c;
+            }
+        }
+    }
+
+    /// Returns the number of iterates that each channel gets from an orbit whose first
+    /// `count` iterates (including the initial 0) stayed inside the escape radius.
+    fn orbit_lengths(&self, count: u32) -> [u32; 3] {
+        self.iteration_limits.map(|limit| match self.orbits {
+            OrbitSelection::Escaping if count < limit => count - 1,
+            OrbitSelection::Bounded if count >= limit => limit - 1,
+            _ => 0,
+        })
+    }
+}
+
+/// Counts how many iterates of `z = z^2 + c` (starting with `z = 0`) stay inside the escape
+/// radius, up to `max_iters`.
+fn escape_counts<const N: usize>(c: SimdComplex<N>, max_iters: u32) -> Array<u32, N> {
+    let escape_radius_squared = SimdDouble::splat(BuddhabrotRenderer::ESCAPE_RADIUS_SQUARED);
+    let mut z = SimdComplex::default();
+    let mut cnt = SimdCounter::new();
+    for _ in 0..max_iters {
+        cnt.increment_where(z.norm_squared().simd_lt(escape_radius_squared));
+        if !cnt.modified() {
+            break;
+        }
+        z = z * z + c;
+    }
+    cnt.wide_counts()
+}
+
+/// Visits the iterates of the orbit of `c` after the initial 0, along with the channels that
+/// they count for: each channel gets as many iterates as its length.
+fn trace_orbit(c: Complex, lengths: [u32; 3], mut visit: impl FnMut(Complex, [u32; 3])) {
+    let max_length = lengths.into_iter().max().unwrap_or(0);
+    let mut z = Complex::default();
+    for i in 0..max_length {
+        z = z * z + c;
+        visit(z, lengths.map(|length| (i < length) as u32));
+    }
+}
+
+/// Returns whether `c` is inside the main cardioid or the period-2 bulb of the Mandelbrot set.
+fn in_main_bulbs(c: Complex) -> bool {
+    let q = (c.re - 0.25) * (c.re - 0.25) + c.im * c.im;
+    let in_cardioid = q * (q + (c.re - 0.25)) <= 0.25 * c.im * c.im;
+    let in_bulb = (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 0.0625;
+    in_cardioid || in_bulb
+}
+
+/// Maps visit counts to colors with a square root curve, independently for each channel.
+pub fn tone_map(histogram: &[[u32; 3]]) -> Vec<Rgb> {
+    let white_points: [f64; 3] = std::array::from_fn(|channel| {
+        let mut counts = histogram
+            .iter()
+            .map(|counts| counts[channel])
+            .filter(|&count| count > 0)
+            .collect_vec();
+        if counts.is_empty() {
+            return 1.0;
+        }
+        let saturated = (counts.len() as f64 * BuddhabrotRenderer::SATURATED_FRACTION) as usize;
+        let index = counts.len() - 1 - saturated;
+        *counts.select_nth_unstable(index).1 as f64
+    });
+    histogram
+        .iter()
+        .map(|counts| {
+            let [r, g, b] = std::array::from_fn(|channel| {
+                let value = (counts[channel] as f64 / white_points[channel]).min(1.0);
+                (value.sqrt() * 255.0).round() as u8
+            });
+            Rgb(r, g, b)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_counts, in_main_bulbs, trace_orbit, BuddhabrotRenderer};
+    use crate::{
+        rules::{scalar, Bailout},
+        simd::SimdComplex,
+        utils::Complex,
+        view::ComplexPlaneView,
+    };
+
+    const WIDTH: usize = 48;
+    const HEIGHT: usize = 32;
+    const SAMPLES: usize = 20_000;
+
+    fn view() -> ComplexPlaneView {
+        ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.5, 0.0), 3.0)
+    }
+
+    #[test]
+    fn escape_counts_match_mandelbrot() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..1000 {
+            let cs: [Complex; 4] =
+                std::array::from_fn(|_| Complex::new(rng.f64() * 4.0 - 2.0, rng.f64() * 4.0 - 2.0));
+            let simd_cs = SimdComplex {
+                re: cs.map(|c| c.re).into(),
+                im: cs.map(|c| c.im).into(),
+            };
+            let expected = cs.map(|c| scalar::mandelbrot(c, Bailout::DEFAULT) as u32);
+            assert_eq!(escape_counts(simd_cs, u8::MAX as u32), expected);
+        }
+    }
+
+    #[test]
+    fn orbits_are_traced_per_channel() {
+        let mut visits = vec![];
+        // The orbit of -2 is 0, -2, 2, 2, ...
+        trace_orbit(Complex::new(-2.0, 0.0), [1, 3, 0], |z, channels| {
+            visits.push((z.re, channels))
+        });
+        assert_eq!(
+            visits,
+            [(-2.0, [1, 1, 0]), (2.0, [0, 1, 0]), (2.0, [0, 1, 0])]
+        );
+    }
+
+    #[test]
+    fn main_bulbs_are_detected() {
+        assert!(in_main_bulbs(Complex::new(0.0, 0.0)));
+        assert!(in_main_bulbs(Complex::new(-1.0, 0.1)));
+        assert!(in_main_bulbs(Complex::new(0.24, 0.0)));
+        assert!(!in_main_bulbs(Complex::new(0.26, 0.0)));
+        assert!(!in_main_bulbs(Complex::new(-1.3, 0.0)));
+        assert!(!in_main_bulbs(Complex::new(-0.75, 0.2)));
+    }
+
+    #[test]
+    fn histogram_does_not_depend_on_scheduling() {
+        let renderer = BuddhabrotRenderer::buddhabrot(50).with_samples(SAMPLES);
+        let pool = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+        };
+        let single = pool(1).install(|| renderer.histogram(&view()));
+        let multi = pool(4).install(|| renderer.histogram(&view()));
+        assert_eq!(single, multi);
+        assert!(single.iter().any(|counts| counts[0] > 0));
+    }
+
+    #[test]
+    fn nebulabrot_channels_grow_with_iteration_limit() {
+        let histogram = BuddhabrotRenderer::nebulabrot([20, 20, 100])
+            .with_samples(SAMPLES)
+            .histogram(&view());
+        assert!(histogram.iter().all(|counts| counts[0] == counts[1]));
+        let total = |channel: usize| histogram.iter().map(|c| c[channel] as u64).sum::<u64>();
+        assert!(total(2) > total(0));
+    }
+
+    #[test]
+    fn anti_buddhabrot_traces_bounded_orbits() {
+        // Every orbit in the main cardioid converges, so it never leaves the escape radius
+        let view = view();
+        let histogram = BuddhabrotRenderer::anti_buddhabrot(50)
+            .with_samples(SAMPLES)
+            .histogram(&view);
+        let total: u64 = histogram.iter().map(|counts| counts[0] as u64).sum();
+        assert!(total > 0);
+        let complex_of = view.pixel_mapper();
+        let outside = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| complex_of(x, y).norm_sqr() > 4.5)
+            .map(|(x, y)| histogram[y * WIDTH + x][0] as u64)
+            .sum::<u64>();
+        assert_eq!(outside, 0);
+    }
+
+    #[test]
+    fn pixel_locator_inverts_pixel_mapper() {
+        let view = view();
+        let (to_complex, to_pixel) = (view.pixel_mapper(), view.pixel_locator());
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                assert_eq!(to_pixel(to_complex(x, y)), Some((x, y)));
+            }
+        }
+        assert_eq!(to_pixel(Complex::new(10.0, 0.0)), None);
+        assert_eq!(to_pixel(Complex::new(f64::NAN, 0.0)), None);
+    }
+}
@@ -1,6 +1,13 @@
 #![allow(dead_code)]
 
-use crate::rules::MAX_ITERS;
+use crate::{
+    image::Image,
+    polynomial::Polynomial,
+    rules::{OrbitTrap, TrapShape, MAX_ITERS},
+    simd::{Array, SimdComplex, SimdDouble},
+    utils::Complex,
+};
+use nalgebra::ComplexField;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Rgb(pub u8, pub u8, pub u8);
@@ -15,7 +22,25 @@ pub fn grayscale(value: u8) -> Rgb {
     Rgb(value, value, value)
 }
 
-#[derive(Debug)]
+/// Converts a color from HSV, with the hue in turns (wrapping around) and the other components in `[0, 1]`.
+pub fn hsv(hue: f64, saturation: f64, value: f64) -> Rgb {
+    let sector = hue.rem_euclid(1.0) * 6.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    let to_u8 = |c: f64| ((c + m) * 255.0).round() as u8;
+    Rgb(to_u8(r), to_u8(g), to_u8(b))
+}
+
+#[derive(Debug, Clone)]
 pub struct Palette {
     colors: [Rgb; Self::MAX_COLORS],
 }
@@ -23,7 +48,8 @@ pub struct Palette {
 impl Palette {
     const MAX_COLORS: usize = MAX_ITERS as usize + 1;
 
-    pub fn from_gradient(gradient: &[Rgb]) -> Self {
+    /// Creates a palette from evenly spaced colors, interpolated in the given color space.
+    pub fn from_gradient(gradient: &[Rgb], space: ColorSpace) -> Self {
         assert!(
             gradient.len() >= 2 && gradient.len() <= Self::MAX_COLORS,
             "Color gradient must specify between 2 and {} colors",
@@ -34,109 +60,1504 @@ impl Palette {
         let range_len = Self::MAX_COLORS / range_count;
         let mut colors = [Rgb(0, 0, 0); Self::MAX_COLORS];
 
-        for i in 0..Self::MAX_COLORS {
+        for (i, color) in colors.iter_mut().enumerate() {
             let range_index = (i / range_len).min(range_count - 1);
             let alpha = (i - range_index * range_len) as f64 / range_len as f64;
             let (start, end) = (gradient[range_index], gradient[range_index + 1]);
-            colors[i] = Self::interpolate_rgb(start, end, alpha);
+            *color = space.interpolate(start, end, alpha);
         }
 
         Self { colors }
     }
 
+    /// Creates a palette from colors at the given positions, interpolated in the given color space.
+    /// Positions go from 0 for the first palette color to 1 for the last one, in increasing order.
+    /// Palette colors before the first stop or after the last one have the color of that stop.
+    pub fn from_stops(stops: &[(f64, Rgb)], space: ColorSpace) -> Self {
+        assert!(
+            !stops.is_empty(),
+            "Color gradient must specify at least one stop"
+        );
+        assert!(
+            stops
+                .iter()
+                .all(|&(position, _)| (0.0..=1.0).contains(&position))
+                && stops.windows(2).all(|w| w[0].0 <= w[1].0),
+            "Color gradient stops must be increasing positions between 0 and 1"
+        );
+
+        Self::from_fn(|position| {
+            let next = stops.partition_point(|&(stop, _)| stop <= position);
+            match (next.checked_sub(1).map(|i| stops[i]), stops.get(next)) {
+                (Some((_, color)), None) | (None, Some(&(_, color))) => color,
+                (Some((start_position, start)), Some(&(end_position, end))) => {
+                    let alpha = (position - start_position) / (end_position - start_position);
+                    space.interpolate(start, end, alpha)
+                }
+                (None, None) => unreachable!("there is at least one stop"),
+            }
+        })
+    }
+
+    /// Creates a palette from a function that gives the color at each position,
+    /// from 0 for the first palette color to 1 for the last one.
+    pub fn from_fn(color_at: impl Fn(f64) -> Rgb) -> Self {
+        Self {
+            colors: std::array::from_fn(|i| color_at(i as f64 / MAX_ITERS as f64)),
+        }
+    }
+
+    /// Creates a procedural palette where each RGB component follows `a + b·cos(2π(c·t + d))`.
+    pub fn from_cosine(cosine: Cosine) -> Self {
+        Self::from_fn(|t| cosine.color_at(t))
+    }
+
+    pub fn from_colormap(colormap: Colormap) -> Self {
+        Self::from_fn(|t| colormap.color_at(t))
+    }
+
     pub fn color(&self, value: u8) -> Rgb {
         self.colors[value as usize]
     }
 
+    /// Returns the palette rotated by `offset` colors, for color cycling.
+    /// The color of `MAX_ITERS`, which is used inside the fractals, stays in place.
+    pub fn rotated(&self, offset: u8) -> Self {
+        let cycle_len = MAX_ITERS as usize;
+        let mut colors = self.colors;
+        for (i, color) in colors[..cycle_len].iter_mut().enumerate() {
+            *color = self.colors[(i + offset as usize) % cycle_len];
+        }
+        Self { colors }
+    }
+
+    /// Returns the colors of every palette value, in order.
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
     pub fn color_array<const N: usize>(&self, values: [u8; N]) -> [Rgb; N] {
         values.map(|v| self.color(v))
     }
+}
+
+/// The color space that palette gradients are interpolated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Blends the sRGB components directly. It is not gamma-correct, so midtones come out darker.
+    Srgb,
+    /// Blends the linear light intensities of the sRGB components.
+    LinearRgb,
+    /// Blends in OKLab, where equal steps look about equally different.
+    Oklab,
+    /// Blends in the polar form of OKLab, going around the shorter way between the hues.
+    Oklch,
+    /// Blends in HSV, going around the shorter way between the hues.
+    Hsv,
+}
+
+impl ColorSpace {
+    pub const ALL: [Self; 5] = [
+        Self::Srgb,
+        Self::LinearRgb,
+        Self::Oklab,
+        Self::Oklch,
+        Self::Hsv,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Srgb => "srgb",
+            Self::LinearRgb => "linear-rgb",
+            Self::Oklab => "oklab",
+            Self::Oklch => "oklch",
+            Self::Hsv => "hsv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|space| space.name() == name)
+    }
 
-    fn interpolate_rgb(start: Rgb, end: Rgb, alpha: f64) -> Rgb {
+    /// Returns the color at `alpha` of the way from `start` to `end`.
+    pub fn interpolate(self, start: Rgb, end: Rgb, alpha: f64) -> Rgb {
         if alpha >= 1.0 {
             return end;
         }
-        Rgb(
-            Self::interpolate_u8(start.0, end.0, alpha),
-            Self::interpolate_u8(start.1, end.1, alpha),
-            Self::interpolate_u8(start.2, end.2, alpha),
+        match self {
+            Self::Srgb => {
+                let lerp_u8 = |start: u8, end: u8| lerp(start as f64, end as f64, alpha) as u8;
+                Rgb(
+                    lerp_u8(start.0, end.0),
+                    lerp_u8(start.1, end.1),
+                    lerp_u8(start.2, end.2),
+                )
+            }
+            Self::LinearRgb => {
+                let (start, end) = (to_linear_rgb(start), to_linear_rgb(end));
+                from_linear_rgb(std::array::from_fn(|i| lerp(start[i], end[i], alpha)))
+            }
+            Self::Oklab => {
+                let (start, end) = (to_oklab(start), to_oklab(end));
+                from_oklab(std::array::from_fn(|i| lerp(start[i], end[i], alpha)))
+            }
+            Self::Oklch => {
+                let ([l1, a1, b1], [l2, a2, b2]) = (to_oklab(start), to_oklab(end));
+                let (chroma1, chroma2) = (a1.hypot(b1), a2.hypot(b2));
+                let hue = lerp_hue(
+                    b1.atan2(a1) / TAU,
+                    chroma1 > ACHROMATIC_CHROMA,
+                    b2.atan2(a2) / TAU,
+                    chroma2 > ACHROMATIC_CHROMA,
+                    alpha,
+                ) * TAU;
+                let chroma = lerp(chroma1, chroma2, alpha);
+                from_oklab([lerp(l1, l2, alpha), chroma * hue.cos(), chroma * hue.sin()])
+            }
+            Self::Hsv => {
+                let ([h1, s1, v1], [h2, s2, v2]) = (to_hsv(start), to_hsv(end));
+                let hue = lerp_hue(h1, s1 > 0.0, h2, s2 > 0.0, alpha);
+                hsv(hue, lerp(s1, s2, alpha), lerp(v1, v2, alpha))
+            }
+        }
+    }
+}
+
+/// OKLab colors with a smaller chroma are treated as gray, so their hue is ignored.
+const ACHROMATIC_CHROMA: f64 = 1e-4;
+const TAU: f64 = std::f64::consts::TAU;
+
+/// The parameters of a cosine palette, `a + b·cos(2π(c·t + d))` for each of the red, green
+/// and blue components, where `t` goes from 0 to 1 along the palette.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cosine {
+    /// The middle value of each component (`a`)
+    pub offset: [f64; 3],
+    /// How far each component swings around its middle value (`b`)
+    pub amplitude: [f64; 3],
+    /// How many times each component oscillates along the palette (`c`)
+    pub frequency: [f64; 3],
+    /// Where each oscillation starts, in turns (`d`)
+    pub phase: [f64; 3],
+}
+
+impl Cosine {
+    /// A rainbow that goes once around the hues.
+    pub const DEFAULT: Self = Self {
+        offset: [0.5; 3],
+        amplitude: [0.5; 3],
+        frequency: [1.0; 3],
+        phase: [0.0, 1.0 / 3.0, 2.0 / 3.0],
+    };
+
+    /// Returns the color at `t` along the palette. Components outside `[0, 1]` are clamped.
+    pub fn color_at(&self, t: f64) -> Rgb {
+        from_unit_rgb(std::array::from_fn(|i| {
+            self.offset[i]
+                + self.amplitude[i] * (TAU * (self.frequency[i] * t + self.phase[i])).cos()
+        }))
+    }
+}
+
+impl Default for Cosine {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Scientific colormaps, which are safe for readers with color vision deficiencies.
+/// All but `Turbo` are perceptually uniform, with lightness that increases along the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Inferno,
+    Plasma,
+    /// A version of viridis that also looks the same to readers with red-green color blindness.
+    Cividis,
+    /// A rainbow that avoids the false boundaries of HSV rainbows. Its lightness rises and falls.
+    Turbo,
+}
+
+impl Colormap {
+    pub const ALL: [Self; 6] = [
+        Self::Viridis,
+        Self::Magma,
+        Self::Inferno,
+        Self::Plasma,
+        Self::Cividis,
+        Self::Turbo,
+    ];
+
+    /// Polynomial fits of the matplotlib colormaps, with the coefficients of `t⁰` to `t⁶`.
+    const VIRIDIS: [[f64; 3]; 7] = [
+        [0.2777273272234177, 0.005407344544966578, 0.3340998053353061],
+        [0.1050930431085774, 1.404613529898575, 1.384590162594685],
+        [-0.3308618287255563, 0.214847559468213, 0.09509516302823659],
+        [-4.634230498983486, -5.799100973351585, -19.33244095627987],
+        [6.228269936347081, 14.17993336680509, 56.69055260068105],
+        [4.776384997670288, -13.74514537774601, -65.35303263337234],
+        [-5.435455855934631, 4.645852612178535, 26.3124352495832],
+    ];
+    const MAGMA: [[f64; 3]; 7] = [
+        [
+            -0.002136485053939582,
+            -0.000749655052795221,
+            -0.005386127855323933,
+        ],
+        [0.2516605407371642, 0.6775232436837668, 2.494026599312351],
+        [8.353717279216625, -3.577719514958484, 0.3144679030132573],
+        [-27.66873308576866, 14.26473078096533, -13.64921318813922],
+        [52.17613981234068, -27.94360607168351, 12.94416944238394],
+        [-50.76852536473588, 29.04658282127291, 4.23415299384598],
+        [18.65570506591883, -11.48977351997711, -5.601961508734096],
+    ];
+    const INFERNO: [[f64; 3]; 7] = [
+        [
+            0.0002189403691192265,
+            0.001651004631001012,
+            -0.01948089843709184,
+        ],
+        [0.1065134194856116, 0.5639564367884091, 3.932712388889277],
+        [11.60249308247187, -3.972853965665698, -15.9423941062914],
+        [-41.70399613139459, 17.43639888205313, 44.35414519872813],
+        [77.162935699427, -33.40235894210092, -81.80730925738993],
+        [-71.31942824499214, 32.62606426397723, 73.20951985803202],
+        [25.13112622477341, -12.24266895238567, -23.07032500287172],
+    ];
+    const PLASMA: [[f64; 3]; 7] = [
+        [0.05873234392399702, 0.02333670892565664, 0.5433401826748754],
+        [2.176514634195958, 0.2383834171260182, 0.7539604599784036],
+        [-2.689460476458034, -7.455851135738909, 3.110799939717086],
+        [6.130348345893603, 42.3461881477227, -28.51885465332158],
+        [-11.10743619062271, -82.66631109428045, 60.13984767418263],
+        [10.02306557647065, 71.4136177009535, -54.07218655560067],
+        [-3.658713842777788, -22.93153465461149, 18.19190778539828],
+    ];
+    /// The polynomial approximation published with turbo, with the coefficients of `t⁰` to `t⁵`.
+    const TURBO: [[f64; 3]; 6] = [
+        [0.13572138, 0.09140261, 0.10667330],
+        [4.61539260, 2.19418839, 12.64194608],
+        [-42.66032258, 4.84296658, -60.58204836],
+        [132.13108234, -14.18503333, 110.36276771],
+        [-152.94239396, 4.27729857, -89.90310912],
+        [59.28637943, 2.82956604, 27.34824973],
+    ];
+    /// The reference colors of cividis at 18 even steps, interpolated in OKLab.
+    /// A polynomial cannot follow the red channel, which stays at zero until the 3rd step.
+    const CIVIDIS: [Rgb; 18] = [
+        Rgb(0, 32, 76),
+        Rgb(0, 42, 102),
+        Rgb(0, 52, 110),
+        Rgb(39, 63, 108),
+        Rgb(60, 74, 107),
+        Rgb(76, 85, 107),
+        Rgb(91, 95, 109),
+        Rgb(104, 106, 112),
+        Rgb(117, 117, 117),
+        Rgb(131, 129, 120),
+        Rgb(146, 140, 120),
+        Rgb(161, 152, 118),
+        Rgb(176, 165, 114),
+        Rgb(192, 177, 109),
+        Rgb(209, 191, 102),
+        Rgb(225, 204, 92),
+        Rgb(243, 219, 79),
+        Rgb(255, 233, 69),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Viridis => "viridis",
+            Self::Magma => "magma",
+            Self::Inferno => "inferno",
+            Self::Plasma => "plasma",
+            Self::Cividis => "cividis",
+            Self::Turbo => "turbo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|colormap| colormap.name() == name)
+    }
+
+    /// Returns the color at `t` along the colormap, from 0 to 1.
+    pub fn color_at(self, t: f64) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let polynomial = |coefficients: &[[f64; 3]]| {
+            from_unit_rgb(std::array::from_fn(|i| {
+                coefficients
+                    .iter()
+                    .rev()
+                    .fold(0.0, |sum, coefficient| sum * t + coefficient[i])
+            }))
+        };
+        match self {
+            Self::Viridis => polynomial(&Self::VIRIDIS),
+            Self::Magma => polynomial(&Self::MAGMA),
+            Self::Inferno => polynomial(&Self::INFERNO),
+            Self::Plasma => polynomial(&Self::PLASMA),
+            Self::Turbo => polynomial(&Self::TURBO),
+            Self::Cividis => {
+                let position = t * (Self::CIVIDIS.len() - 1) as f64;
+                let index = (position as usize).min(Self::CIVIDIS.len() - 2);
+                ColorSpace::Oklab.interpolate(
+                    Self::CIVIDIS[index],
+                    Self::CIVIDIS[index + 1],
+                    position - index as f64,
+                )
+            }
+        }
+    }
+}
+
+/// Converts components in `[0, 1]` to a color, clamping the ones outside.
+fn from_unit_rgb(rgb: [f64; 3]) -> Rgb {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Rgb(r, g, b)
+}
+
+fn lerp(start: f64, end: f64, alpha: f64) -> f64 {
+    start + (end - start) * alpha
+}
+
+/// Interpolates between hues in turns the shorter way around. Gray colors have no hue,
+/// so the hue of the other color is used instead.
+fn lerp_hue(start: f64, start_has_hue: bool, end: f64, end_has_hue: bool, alpha: f64) -> f64 {
+    match (start_has_hue, end_has_hue) {
+        (true, false) => start,
+        (false, true) => end,
+        _ => {
+            let difference = (end - start + 0.5).rem_euclid(1.0) - 0.5;
+            start + difference * alpha
+        }
+    }
+}
+
+fn to_linear_rgb(color: Rgb) -> [f64; 3] {
+    [color.0, color.1, color.2].map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+fn from_linear_rgb(rgb: [f64; 3]) -> Rgb {
+    let [r, g, b] = rgb.map(|c| {
+        let c = if c <= 0.0031308 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (c * 255.0).round().clamp(0.0, 255.0) as u8
+    });
+    Rgb(r, g, b)
+}
+
+/// Converts a color to OKLab, see <https://bottosson.github.io/posts/oklab/>.
+fn to_oklab(color: Rgb) -> [f64; 3] {
+    let [r, g, b] = to_linear_rgb(color);
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// Converts a color from OKLab. Colors outside of the sRGB gamut are clamped.
+fn from_oklab([lightness, a, b]: [f64; 3]) -> Rgb {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    from_linear_rgb([
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ])
+}
+
+/// Converts a color to HSV, the inverse of `hsv`.
+pub fn to_hsv(color: Rgb) -> [f64; 3] {
+    let [r, g, b] = [color.0, color.1, color.2].map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+    let sector = if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+    [(sector / 6.0).rem_euclid(1.0), saturation, max]
+}
+
+/// Maps the escape values of a frame to palette values. When zoomed in, most pixels have
+/// escape values in a narrow range, so only a few palette colors would show otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// Escape values are used as they are.
+    Linear,
+    /// Spreads the palette evenly over the pixels of the frame, so that every palette value
+    /// is used by about as many pixels.
+    Equalize,
+    /// Spreads the palette over the escape values of the frame on a logarithmic scale.
+    Log,
+    /// Spreads the palette over the escape values of the frame on a square root scale.
+    Sqrt,
+}
+
+impl Transfer {
+    pub const ALL: [Self; 4] = [Self::Linear, Self::Equalize, Self::Log, Self::Sqrt];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Equalize => "equalize",
+            Self::Log => "log",
+            Self::Sqrt => "sqrt",
+        }
+    }
+
+    /// Builds a table that maps every escape value to a palette value, given the escape values
+    /// of a frame. Escape values of `MAX_ITERS` are inside the fractal and keep their value,
+    /// while the others are spread between 0 and `MAX_ITERS - 1`.
+    pub fn table(self, values: &[u8]) -> [u8; Palette::MAX_COLORS] {
+        let mut table = std::array::from_fn(|v| v as u8);
+        if self == Self::Linear {
+            return table;
+        }
+        let mut histogram = [0usize; Palette::MAX_COLORS];
+        for &value in values.iter().filter(|&&v| v != MAX_ITERS) {
+            histogram[value as usize] += 1;
+        }
+        let Some(min) = histogram.iter().position(|&n| n > 0) else {
+            return table;
+        };
+        let max = histogram.iter().rposition(|&n| n > 0).unwrap_or(min);
+        let range = (max - min) as f64;
+        let to_value = |t: f64| {
+            let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+            (t * (MAX_ITERS - 1) as f64).round() as u8
+        };
+        match self {
+            Self::Linear => (),
+            Self::Equalize => {
+                // The fraction of the pixels above the lowest value with this value or less
+                let lowest = histogram[min];
+                let total: usize = histogram.iter().sum();
+                let mut cumulative = 0;
+                for (value, count) in histogram.iter().enumerate().skip(min) {
+                    cumulative += count;
+                    table[value] = to_value((cumulative - lowest) as f64 / (total - lowest) as f64);
+                }
+            }
+            Self::Log => {
+                for (value, entry) in table.iter_mut().enumerate().skip(min) {
+                    *entry = to_value(((value - min) as f64).ln_1p() / range.ln_1p());
+                }
+            }
+            Self::Sqrt => {
+                for (value, entry) in table.iter_mut().enumerate().skip(min) {
+                    *entry = to_value(((value - min) as f64 / range).sqrt());
+                }
+            }
+        }
+        table[MAX_ITERS as usize] = MAX_ITERS;
+        table
+    }
+}
+
+/// Colors Newton fractals by the root each pixel converged to.
+/// Every root gets its own hue, darkened by the number of iterations it took to get there.
+/// Pixels that did not converge to any root are black.
+#[derive(Debug, Clone)]
+pub struct BasinColoring {
+    roots: Vec<Complex>,
+}
+
+impl BasinColoring {
+    /// Distance below which roots are merged, and the smallest distance between a converged
+    /// value and the root it is assigned to.
+    const ROOT_TOLERANCE: f64 = 1e-3;
+    /// Iteration stops within about one tolerance of a root (more for multiple roots, which
+    /// converge slowly), so converged values are assigned within this many tolerances.
+    const TOLERANCES_PER_ROOT: f64 = 10.0;
+    const SATURATION: f64 = 0.8;
+    /// Brightness is multiplied by this factor for every iteration.
+    const SHADE_FALLOFF: f64 = 0.94;
+    const MIN_SHADE: f64 = 0.15;
+
+    /// Creates a coloring with one basin for each distinct root of the polynomial.
+    pub fn from_polynomial(polynomial: &Polynomial) -> Result<Self, String> {
+        polynomial.roots().map(Self::new)
+    }
+
+    /// Creates a coloring for the given roots. Roots closer than the tolerance,
+    /// like the copies of a multiple root, are merged into a single basin.
+    pub fn new(roots: impl IntoIterator<Item = Complex>) -> Self {
+        let mut distinct: Vec<Complex> = Vec::new();
+        for root in roots {
+            if distinct
+                .iter()
+                .all(|&r| (r - root).modulus() >= Self::ROOT_TOLERANCE)
+            {
+                distinct.push(root);
+            }
+        }
+        Self { roots: distinct }
+    }
+
+    pub fn roots(&self) -> &[Complex] {
+        &self.roots
+    }
+
+    /// Returns the index of the root closest to `z`, if `z` is close enough to have converged
+    /// to it with the given convergence tolerance.
+    pub fn root_index(&self, z: Complex, tolerance: f64) -> Option<usize> {
+        let radius = (Self::TOLERANCES_PER_ROOT * tolerance).max(Self::ROOT_TOLERANCE);
+        self.roots
+            .iter()
+            .map(|&r| (r - z).modulus())
+            .enumerate()
+            .filter(|&(_, distance)| distance < radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    pub fn color(&self, count: u8, z: Complex, tolerance: f64) -> Rgb {
+        match self.root_index(z, tolerance) {
+            Some(i) => {
+                let hue = i as f64 / self.roots.len() as f64;
+                let shade = Self::SHADE_FALLOFF.powi(count as i32).max(Self::MIN_SHADE);
+                hsv(hue, Self::SATURATION, shade)
+            }
+            None => Rgb(0, 0, 0),
+        }
+    }
+
+    pub fn color_array<const N: usize>(
+        &self,
+        counts: Array<u8, N>,
+        roots: SimdComplex<N>,
+        tolerance: f64,
+    ) -> Array<Rgb, N> {
+        std::array::from_fn(|i| {
+            let z = Complex::new(roots.re[i], roots.im[i]);
+            self.color(counts[i], z, tolerance)
+        })
+    }
+}
+
+/// Colors pixels by their estimated distance to the boundary of a fractal, relative to the
+/// pixel size. Pixels inside the fractal have a distance of 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceColoring {
+    /// Width of the boundary, in pixels.
+    pub thickness: f64,
+}
+
+impl DistanceColoring {
+    pub const DEFAULT: Self = Self { thickness: 1.0 };
+    /// Number of palette colors for every doubling of the distance.
+    const PALETTE_STEPS_PER_OCTAVE: f64 = 16.0;
+    /// Positions of the samples used for antialiasing, in pixels from the pixel center.
+    const SUBSAMPLE_OFFSETS: [(f64, f64); 4] =
+        [(-0.25, -0.25), (0.25, -0.25), (-0.25, 0.25), (0.25, 0.25)];
+
+    /// Returns how much of the pixel is covered by the boundary. It fades out linearly
+    /// with the distance, which already smooths the edges of the boundary.
+    pub fn coverage(&self, distance: f64, pixel_size: f64) -> f64 {
+        1.0 - (distance / (self.thickness * pixel_size)).clamp(0.0, 1.0)
+    }
+
+    /// Returns whether the edge of the boundary may cross the pixel, in which case
+    /// a single sample is not enough to tell its coverage.
+    pub fn needs_supersampling(&self, distance: f64, pixel_size: f64) -> bool {
+        distance > 0.0 && distance < self.thickness * pixel_size
+    }
+
+    /// Draws the boundary and the interior in black on a white background.
+    pub fn boundary(&self, distance: f64, pixel_size: f64) -> Rgb {
+        Self::boundary_shade(self.coverage(distance, pixel_size))
+    }
+
+    /// Same as `boundary`, for the points `z` whose distances are given by `estimate`.
+    /// Pixels that need supersampling are estimated again at several points and averaged.
+    pub fn boundary_array<const N: usize>(
+        &self,
+        z: SimdComplex<N>,
+        pixel_size: f64,
+        estimate: impl Fn(SimdComplex<N>) -> SimdDouble<N>,
+    ) -> Array<Rgb, N> {
+        let distances = estimate(z);
+        let mut coverage: Array<f64, N> =
+            std::array::from_fn(|i| self.coverage(distances[i], pixel_size));
+        let needs_supersampling: Array<bool, N> =
+            std::array::from_fn(|i| self.needs_supersampling(distances[i], pixel_size));
+        if needs_supersampling.contains(&true) {
+            let mut sums = [0.0; N];
+            for (dx, dy) in Self::SUBSAMPLE_OFFSETS {
+                let offset = SimdComplex::splat(dx * pixel_size, dy * pixel_size);
+                let distances = estimate(z + offset);
+                for (sum, distance) in sums.iter_mut().zip(distances.to_array()) {
+                    *sum += self.coverage(distance, pixel_size);
+                }
+            }
+            for i in (0..N).filter(|&i| needs_supersampling[i]) {
+                coverage[i] = sums[i] / Self::SUBSAMPLE_OFFSETS.len() as f64;
+            }
+        }
+        coverage.map(Self::boundary_shade)
+    }
+
+    fn boundary_shade(coverage: f64) -> Rgb {
+        grayscale(((1.0 - coverage) * 255.0).round() as u8)
+    }
+
+    /// Picks palette indices by the logarithm of the distance in pixels, so that
+    /// the colors around the boundary look the same at every zoom level.
+    /// The boundary and the interior get the last index of the palette.
+    pub fn palette_index(&self, distance: f64, pixel_size: f64) -> u8 {
+        let octaves = (distance / (self.thickness * pixel_size)).log2().max(0.0);
+        let steps = (octaves * Self::PALETTE_STEPS_PER_OCTAVE).min(MAX_ITERS as f64);
+        MAX_ITERS - steps as u8
+    }
+
+    pub fn palette_index_array<const N: usize>(
+        &self,
+        distances: SimdDouble<N>,
+        pixel_size: f64,
+    ) -> Array<u8, N> {
+        distances
+            .to_array()
+            .map(|distance| self.palette_index(distance, pixel_size))
+    }
+
+    pub fn palette_color(&self, palette: &Palette, distance: f64, pixel_size: f64) -> Rgb {
+        palette.color(self.palette_index(distance, pixel_size))
+    }
+
+    pub fn palette_color_array<const N: usize>(
+        &self,
+        palette: &Palette,
+        distances: SimdDouble<N>,
+        pixel_size: f64,
+    ) -> Array<Rgb, N> {
+        distances
+            .to_array()
+            .map(|distance| self.palette_color(palette, distance, pixel_size))
+    }
+}
+
+/// Colors pixels by how close their orbits came to an orbit trap, see `OrbitTrap`.
+pub struct TrapColoring {
+    /// Drawn in the square of the `Image` trap, stretched to fill it.
+    pub image: Option<Image>,
+}
+
+impl TrapColoring {
+    /// Picks palette indices by the distance relative to the trap size,
+    /// from the first color on the trap to the last color far away from it.
+    pub fn palette_index(&self, trap: OrbitTrap, distance: f64) -> u8 {
+        if !distance.is_finite() {
+            return MAX_ITERS;
+        }
+        let position = distance / (distance + trap.size);
+        (position * MAX_ITERS as f64).round() as u8
+    }
+
+    pub fn palette_color(&self, palette: &Palette, trap: OrbitTrap, distance: f64) -> Rgb {
+        palette.color(self.palette_index(trap, distance))
+    }
+
+    /// Orbits trapped by the `Image` trap get the image pixel at the point that was trapped,
+    /// given by its offset from the trap center. Other orbits use the palette.
+    pub fn image_color(&self, trap: OrbitTrap, distance: f64, closest: Complex) -> Option<Rgb> {
+        match &self.image {
+            Some(image)
+                if trap.shape == TrapShape::Image
+                    && distance == 0.0
+                    && !image.pixels.is_empty() =>
+            {
+                let pixel =
+                    |position: f64, len: usize| ((position * len as f64) as usize).min(len - 1);
+                let x = pixel(closest.re / trap.size + 0.5, image.width);
+                let y = pixel(0.5 - closest.im / trap.size, image.height);
+                Some(image.pixels[y * image.width + x])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn color(
+        &self,
+        palette: &Palette,
+        trap: OrbitTrap,
+        distance: f64,
+        closest: Complex,
+    ) -> Rgb {
+        self.image_color(trap, distance, closest)
+            .unwrap_or_else(|| self.palette_color(palette, trap, distance))
+    }
+
+    pub fn color_array<const N: usize>(
+        &self,
+        palette: &Palette,
+        trap: OrbitTrap,
+        distances: SimdDouble<N>,
+        closest: SimdComplex<N>,
+    ) -> Array<Rgb, N> {
+        std::array::from_fn(|i| {
+            let point = Complex::new(closest.re[i], closest.im[i]);
+            self.color(palette, trap, distances[i], point)
+        })
+    }
+}
+
+/// Shades colors as if the fractal were a relief lit from a distant light. The slope of the
+/// relief at each point comes from the final `z` of the iteration and its derivative `dz`,
+/// which gives the embossed look. Points inside the fractal are left unshaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    /// Direction of the light in the plane, in radians counterclockwise from the real axis.
+    pub azimuth: f64,
+    /// Angle of the light above the plane, in radians.
+    pub elevation: f64,
+    /// Height of the relief. A height of 0 is flat, larger heights have steeper slopes.
+    pub height: f64,
+    /// Strength of the specular highlights. A strength of 0 has no highlights.
+    pub specular: f64,
+}
+
+impl Lighting {
+    pub const DEFAULT: Self = Self {
+        azimuth: std::f64::consts::FRAC_PI_4,
+        elevation: std::f64::consts::FRAC_PI_4,
+        height: 1.0,
+        specular: 0.5,
+    };
+    /// Fraction of the brightness that does not depend on the light.
+    const AMBIENT: f64 = 0.25;
+    /// Sharpness of the specular highlights.
+    const SHININESS: i32 = 20;
+
+    /// Returns the brightness factor and the specular highlight for a relief whose
+    /// steepest descent in the plane is along `direction`.
+    pub fn intensity(&self, direction: Complex) -> (f64, f64) {
+        let direction = direction / direction.modulus();
+        let normal = normalize([direction.re * self.height, direction.im * self.height, 1.0]);
+        let light = [
+            self.elevation.cos() * self.azimuth.cos(),
+            self.elevation.cos() * self.azimuth.sin(),
+            self.elevation.sin(),
+        ];
+        // The viewer is straight above the plane
+        let halfway = normalize([light[0], light[1], light[2] + 1.0]);
+        let diffuse = dot(normal, light).max(0.0);
+        let highlight = dot(normal, halfway).max(0.0).powi(Self::SHININESS);
+        (
+            Self::AMBIENT + (1.0 - Self::AMBIENT) * diffuse,
+            self.specular * highlight,
         )
     }
 
-    fn interpolate_u8(start: u8, end: u8, alpha: f64) -> u8 {
-        let start = start as f64;
-        let end = end as f64;
-        (start + (end - start) * alpha) as u8
+    /// Returns the shade of a point that took `count` iterations to reach `z` with derivative `dz`,
+    /// so that it can be applied to any color picked for the point.
+    pub fn shade_of(&self, count: u8, z: Complex, dz: Complex) -> Shade {
+        let direction = z / dz;
+        if count == MAX_ITERS || !direction.re.is_finite() || !direction.im.is_finite() {
+            return Shade::NONE;
+        }
+        let (brightness, highlight) = self.intensity(direction);
+        Shade {
+            brightness,
+            highlight,
+        }
+    }
+
+    pub fn shade_of_array<const N: usize>(
+        &self,
+        counts: Array<u8, N>,
+        z: SimdComplex<N>,
+        dz: SimdComplex<N>,
+    ) -> Array<Shade, N> {
+        std::array::from_fn(|i| {
+            self.shade_of(
+                counts[i],
+                Complex::new(z.re[i], z.im[i]),
+                Complex::new(dz.re[i], dz.im[i]),
+            )
+        })
     }
+
+    /// Shades `color`, which was picked for a point that took `count` iterations
+    /// to reach `z` with derivative `dz`.
+    pub fn shade(&self, color: Rgb, count: u8, z: Complex, dz: Complex) -> Rgb {
+        self.shade_of(count, z, dz).apply(color)
+    }
+
+    pub fn shade_array<const N: usize>(
+        &self,
+        colors: Array<Rgb, N>,
+        counts: Array<u8, N>,
+        z: SimdComplex<N>,
+        dz: SimdComplex<N>,
+    ) -> Array<Rgb, N> {
+        std::array::from_fn(|i| {
+            self.shade(
+                colors[i],
+                counts[i],
+                Complex::new(z.re[i], z.im[i]),
+                Complex::new(dz.re[i], dz.im[i]),
+            )
+        })
+    }
+}
+
+/// The brightness factor and specular highlight of a point of a lit relief, see `Lighting`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shade {
+    pub brightness: f64,
+    pub highlight: f64,
+}
+
+impl Shade {
+    /// Leaves colors unchanged, like the interior of the fractal.
+    pub const NONE: Self = Self {
+        brightness: 1.0,
+        highlight: 0.0,
+    };
+
+    pub fn apply(self, color: Rgb) -> Rgb {
+        let channel = |c: u8| {
+            (c as f64 * self.brightness + 255.0 * self.highlight)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        Rgb(channel(color.0), channel(color.1), channel(color.2))
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let norm = dot(v, v).sqrt();
+    v.map(|x| x / norm)
 }
 
 pub mod palettes {
-    use super::{Palette, Rgb};
+    use super::{ColorSpace, Colormap, Cosine, Palette, Rgb};
     use once_cell::sync::Lazy;
 
     pub static CYAN: Lazy<Palette> = Lazy::new(|| {
-        Palette::from_gradient(&[
-            Rgb(0, 0, 0),
-            Rgb(0, 35, 66),
-            Rgb(0, 56, 89),
-            Rgb(0, 78, 114),
-            Rgb(0, 102, 139),
-            Rgb(0, 127, 165),
-            Rgb(0, 152, 187),
-            Rgb(0, 177, 205),
-            Rgb(0, 203, 220),
-            Rgb(0, 229, 231),
-            Rgb(0, 255, 238),
-        ])
+        Palette::from_gradient(
+            &[
+                Rgb(0, 0, 0),
+                Rgb(0, 35, 66),
+                Rgb(0, 56, 89),
+                Rgb(0, 78, 114),
+                Rgb(0, 102, 139),
+                Rgb(0, 127, 165),
+                Rgb(0, 152, 187),
+                Rgb(0, 177, 205),
+                Rgb(0, 203, 220),
+                Rgb(0, 229, 231),
+                Rgb(0, 255, 238),
+            ],
+            ColorSpace::Srgb,
+        )
     });
 
     pub static BLUE_GREEN: Lazy<Palette> = Lazy::new(|| {
-        Palette::from_gradient(&[
-            Rgb(97, 179, 255),
-            Rgb(33, 10, 127),
-            Rgb(5, 136, 218),
-            Rgb(11, 204, 49),
-            Rgb(33, 253, 43),
-            Rgb(0, 0, 0),
-        ])
+        Palette::from_gradient(
+            &[
+                Rgb(97, 179, 255),
+                Rgb(33, 10, 127),
+                Rgb(5, 136, 218),
+                Rgb(11, 204, 49),
+                Rgb(33, 253, 43),
+                Rgb(0, 0, 0),
+            ],
+            ColorSpace::Srgb,
+        )
     });
 
     pub static YELLOW_RED: Lazy<Palette> = Lazy::new(|| {
-        Palette::from_gradient(&[
-            Rgb(0, 0, 0),
-            Rgb(250, 255, 0),
-            Rgb(255, 168, 0),
-            Rgb(255, 77, 0),
-            Rgb(153, 41, 41),
-            Rgb(0, 0, 0),
-        ])
+        Palette::from_gradient(
+            &[
+                Rgb(0, 0, 0),
+                Rgb(250, 255, 0),
+                Rgb(255, 168, 0),
+                Rgb(255, 77, 0),
+                Rgb(153, 41, 41),
+                Rgb(0, 0, 0),
+            ],
+            ColorSpace::Srgb,
+        )
     });
 
     pub static RAINBOW_DARK: Lazy<Palette> = Lazy::new(|| {
-        Palette::from_gradient(&[
-            Rgb(0, 0, 0),
-            Rgb(0, 255, 0),
-            Rgb(0, 0, 255),
-            Rgb(255, 0, 255),
-            Rgb(127, 127, 255),
-            Rgb(0, 255, 255),
-            Rgb(255, 255, 0),
-            Rgb(255, 0, 0),
-            Rgb(0, 0, 0),
-        ])
+        Palette::from_gradient(
+            &[
+                Rgb(0, 0, 0),
+                Rgb(0, 255, 0),
+                Rgb(0, 0, 255),
+                Rgb(255, 0, 255),
+                Rgb(127, 127, 255),
+                Rgb(0, 255, 255),
+                Rgb(255, 255, 0),
+                Rgb(255, 0, 0),
+                Rgb(0, 0, 0),
+            ],
+            ColorSpace::Srgb,
+        )
     });
 
     pub static RAINBOW_LIGHT: Lazy<Palette> = Lazy::new(|| {
-        Palette::from_gradient(&[
+        Palette::from_gradient(
+            &[
+                Rgb(255, 255, 255),
+                Rgb(255, 0, 0),
+                Rgb(255, 255, 0),
+                Rgb(0, 255, 255),
+                Rgb(127, 127, 255),
+                Rgb(255, 0, 255),
+                Rgb(0, 0, 255),
+                Rgb(0, 255, 0),
+                Rgb(0, 0, 0),
+            ],
+            ColorSpace::Srgb,
+        )
+    });
+
+    pub static COSINE: Lazy<Palette> = Lazy::new(|| Palette::from_cosine(Cosine::DEFAULT));
+
+    pub static VIRIDIS: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Viridis));
+
+    pub static MAGMA: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Magma));
+
+    pub static INFERNO: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Inferno));
+
+    pub static PLASMA: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Plasma));
+
+    pub static CIVIDIS: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Cividis));
+
+    pub static TURBO: Lazy<Palette> = Lazy::new(|| Palette::from_colormap(Colormap::Turbo));
+
+    /// The built-in palettes with their names.
+    pub fn all() -> [(&'static str, &'static Palette); 12] {
+        [
+            ("cyan", &CYAN),
+            ("blue-green", &BLUE_GREEN),
+            ("yellow-red", &YELLOW_RED),
+            ("rainbow-dark", &RAINBOW_DARK),
+            ("rainbow-light", &RAINBOW_LIGHT),
+            ("cosine", &COSINE),
+            ("viridis", &VIRIDIS),
+            ("magma", &MAGMA),
+            ("inferno", &INFERNO),
+            ("plasma", &PLASMA),
+            ("cividis", &CIVIDIS),
+            ("turbo", &TURBO),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{
+            grayscale, hsv, palettes, to_hsv, to_oklab, BasinColoring, ColorSpace, Colormap,
+            Cosine, DistanceColoring, Lighting, Palette, Rgb, Shade, Transfer, TrapColoring,
+        },
+        image::Image,
+        polynomial::{Polynomial, RootFindingMethod},
+        rules::{OrbitTrap, RootFindingParams, TrapShape, MAX_ITERS},
+        simd::{SimdComplex, SimdDouble},
+        utils::Complex,
+    };
+    use std::{cell::Cell, simd::num::SimdFloat};
+
+    #[test]
+    fn hsv_works() {
+        assert_eq!(hsv(0.0, 1.0, 1.0), Rgb(255, 0, 0));
+        assert_eq!(hsv(1.0 / 3.0, 1.0, 1.0), Rgb(0, 255, 0));
+        assert_eq!(hsv(2.0 / 3.0, 1.0, 1.0), Rgb(0, 0, 255));
+        assert_eq!(hsv(-1.0 / 6.0, 1.0, 1.0), Rgb(255, 0, 255));
+        assert_eq!(hsv(0.5, 0.0, 0.5), Rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn color_spaces_keep_gradient_ends() {
+        let colors = [
+            Rgb(0, 0, 0),
             Rgb(255, 255, 255),
             Rgb(255, 0, 0),
-            Rgb(255, 255, 0),
-            Rgb(0, 255, 255),
-            Rgb(127, 127, 255),
-            Rgb(255, 0, 255),
-            Rgb(0, 0, 255),
+            Rgb(12, 200, 97),
+            Rgb(33, 10, 127),
+            Rgb(128, 128, 128),
+        ];
+        for space in ColorSpace::ALL {
+            for (&start, &end) in colors.iter().zip(colors.iter().rev()) {
+                assert_eq!(space.interpolate(start, end, 0.0), start, "{:?}", space);
+                assert_eq!(space.interpolate(start, end, 1.0), end, "{:?}", space);
+            }
+        }
+    }
+
+    #[test]
+    fn hsv_conversion_round_trips() {
+        for color in [
+            Rgb(255, 0, 0),
+            Rgb(12, 200, 97),
+            Rgb(33, 10, 127),
+            grayscale(77),
+        ] {
+            let [h, s, v] = to_hsv(color);
+            assert_eq!(hsv(h, s, v), color);
+        }
+    }
+
+    #[test]
+    fn linear_midtones_are_brighter_than_srgb() {
+        let (black, white) = (Rgb(0, 0, 0), Rgb(255, 255, 255));
+        let srgb = ColorSpace::Srgb.interpolate(black, white, 0.5);
+        let linear = ColorSpace::LinearRgb.interpolate(black, white, 0.5);
+        assert_eq!(srgb, grayscale(127));
+        assert_eq!(linear, grayscale(188));
+    }
+
+    #[test]
+    fn oklab_steps_have_even_lightness() {
+        let palette = Palette::from_gradient(&[Rgb(0, 0, 80), Rgb(255, 255, 0)], ColorSpace::Oklab);
+        let lightness = |value| to_oklab(palette.color(value))[0];
+        let steps = [0, 64, 128, 192, 255].map(lightness);
+        let first_step = steps[1] - steps[0];
+        for w in steps.windows(2) {
+            assert!(
+                ((w[1] - w[0]) / first_step - 1.0).abs() < 0.05,
+                "{:?}",
+                steps
+            );
+        }
+    }
+
+    #[test]
+    fn hues_go_around_the_shorter_way() {
+        let (magenta_red, orange_red) = (hsv(-0.05, 1.0, 1.0), hsv(0.05, 1.0, 1.0));
+        assert_eq!(
+            ColorSpace::Hsv.interpolate(magenta_red, orange_red, 0.5),
+            Rgb(255, 0, 0)
+        );
+        let middle = ColorSpace::Oklch.interpolate(magenta_red, orange_red, 0.5);
+        assert!(
+            middle.0 > 200 && middle.1 < 100 && middle.2 < 100,
+            "{:?}",
+            middle
+        );
+        // Gray has no hue, so only the lightness and chroma change on the way to red
+        let pink = ColorSpace::Hsv.interpolate(Rgb(255, 255, 255), Rgb(255, 0, 0), 0.5);
+        assert_eq!(pink, Rgb(255, 128, 128));
+    }
+
+    #[test]
+    fn stops_can_be_uneven() {
+        let (black, red, white) = (Rgb(0, 0, 0), Rgb(255, 0, 0), Rgb(255, 255, 255));
+        let palette =
+            Palette::from_stops(&[(0.2, black), (0.8, red), (1.0, white)], ColorSpace::Srgb);
+        assert_eq!(palette.color(0), black);
+        assert_eq!(palette.color(51), black);
+        assert_eq!(palette.color(204), red);
+        assert_eq!(palette.color(MAX_ITERS), white);
+        assert_eq!(palette.color(128), Rgb(128, 0, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "increasing positions")]
+    fn stops_must_be_increasing() {
+        Palette::from_stops(
+            &[(0.5, Rgb(0, 0, 0)), (0.2, Rgb(255, 255, 255))],
+            ColorSpace::Srgb,
+        );
+    }
+
+    #[test]
+    fn linear_transfer_keeps_values() {
+        let table = Transfer::Linear.table(&[10, 11, 12]);
+        assert!(table.iter().enumerate().all(|(i, &v)| v as usize == i));
+    }
+
+    #[test]
+    fn equalized_transfer_spreads_pixels_evenly() {
+        // Most pixels have the lowest value, so it gets the first palette value,
+        // while the few remaining pixels are spread over the rest of the palette
+        let values = [&[10; 90][..], &[11; 5], &[12; 5], &[MAX_ITERS; 20]].concat();
+        let table = Transfer::Equalize.table(&values);
+        assert_eq!(table[10], 0);
+        assert_eq!(table[11], 127);
+        assert_eq!(table[12], MAX_ITERS - 1);
+        assert_eq!(table[MAX_ITERS as usize], MAX_ITERS);
+    }
+
+    #[test]
+    fn log_and_sqrt_transfers_span_the_palette() {
+        let values = [20, 30, 60, MAX_ITERS];
+        for transfer in [Transfer::Log, Transfer::Sqrt] {
+            let table = transfer.table(&values);
+            assert_eq!(table[20], 0);
+            assert_eq!(table[60], MAX_ITERS - 1);
+            assert_eq!(table[MAX_ITERS as usize], MAX_ITERS);
+            // Both curves are concave, so they spread low values the most
+            assert!(table[20..=60].windows(2).all(|w| w[0] <= w[1]));
+            assert!(table[30] > (MAX_ITERS - 1) / 4);
+        }
+        assert!(Transfer::Log.table(&values)[30] > Transfer::Sqrt.table(&values)[30]);
+    }
+
+    #[test]
+    fn transfers_of_interior_frames_keep_values() {
+        for transfer in Transfer::ALL {
+            let table = transfer.table(&[MAX_ITERS; 4]);
+            assert!(table.iter().enumerate().all(|(i, &v)| v as usize == i));
+        }
+    }
+
+    #[test]
+    fn basins_come_from_polynomial_degree() {
+        let basins =
+            BasinColoring::from_polynomial(&Polynomial::from_real(&[1.0, 0.0, 0.0, 0.0, -1.0]))
+                .unwrap();
+        let tolerance = RootFindingParams::DEFAULT.tolerance;
+        assert_eq!(basins.roots().len(), 4);
+        assert_eq!(
+            basins.root_index(Complex::new(1.0, 1e-6), tolerance),
+            Some(0)
+        );
+        assert_eq!(
+            basins.root_index(Complex::new(-1.0, 0.0), tolerance),
+            Some(2)
+        );
+        assert_eq!(basins.root_index(Complex::new(0.5, 0.5), tolerance), None);
+        assert_eq!(basins.root_index(Complex::new(1.05, 0.0), tolerance), None);
+        assert_eq!(basins.root_index(Complex::new(1.05, 0.0), 1e-1), Some(0));
+    }
+
+    #[test]
+    fn basins_are_found_at_every_tolerance() {
+        let polynomial = Polynomial::from_real(&[1.0, 0.0, 0.0, -1.0]);
+        let basins = BasinColoring::from_polynomial(&polynomial).unwrap();
+        let points = (0..100)
+            .flat_map(|y| (0..100).map(move |x| (x, y)))
+            .map(|(x, y)| Complex::new(x as f64 / 25.0 - 2.0, y as f64 / 25.0 - 2.0))
+            .collect::<Vec<_>>();
+        for tolerance in [1e-5, 1e-3, 1e-2, 1e-1] {
+            let params = RootFindingParams {
+                tolerance,
+                ..RootFindingParams::DEFAULT
+            };
+            let found = points
+                .iter()
+                .filter(|&&z| {
+                    let z = SimdComplex::<1>::splat(z.re, z.im);
+                    let (counts, roots) = polynomial.find_root(
+                        RootFindingMethod::Newton,
+                        params,
+                        SimdComplex::default(),
+                        z,
+                    );
+                    basins.color_array(counts, roots, tolerance)[0] != Rgb(0, 0, 0)
+                })
+                .count();
+            assert!(found > 9900, "{} pixels at tolerance {}", found, tolerance);
+        }
+    }
+
+    #[test]
+    fn multiple_roots_share_a_basin() {
+        // (z - 1)^2 (z + 1)
+        let basins =
+            BasinColoring::from_polynomial(&Polynomial::from_real(&[1.0, -1.0, -1.0, 1.0]))
+                .unwrap();
+        assert_eq!(basins.roots().len(), 2);
+    }
+
+    #[test]
+    fn basins_are_shaded_by_iterations() {
+        let basins = BasinColoring::new([Complex::new(1.0, 0.0), Complex::new(-1.0, 0.0)]);
+        let z = Complex::new(-1.0, 0.0);
+        let tolerance = RootFindingParams::DEFAULT.tolerance;
+        let fast = basins.color(2, z, tolerance);
+        let slow = basins.color(20, z, tolerance);
+        assert!(fast.0.max(fast.1).max(fast.2) > slow.0.max(slow.1).max(slow.2));
+        assert_ne!(basins.color(2, Complex::new(1.0, 0.0), tolerance), fast);
+        assert_eq!(
+            basins.color(2, Complex::new(0.0, 0.0), tolerance),
+            Rgb(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn boundary_coverage_fades_with_distance() {
+        let coloring = DistanceColoring { thickness: 2.0 };
+        assert_eq!(coloring.coverage(0.0, 0.1), 1.0);
+        assert_eq!(coloring.coverage(0.1, 0.1), 0.5);
+        assert_eq!(coloring.coverage(0.3, 0.1), 0.0);
+        assert_eq!(coloring.boundary(0.0, 0.1), Rgb(0, 0, 0));
+        assert_eq!(coloring.boundary(1.0, 0.1), Rgb(255, 255, 255));
+        assert!(!coloring.needs_supersampling(0.0, 0.1));
+        assert!(coloring.needs_supersampling(0.1, 0.1));
+        assert!(!coloring.needs_supersampling(0.3, 0.1));
+    }
+
+    #[test]
+    fn only_boundary_pixels_are_supersampled() {
+        let coloring = DistanceColoring::DEFAULT;
+        let pixel_size = 0.1;
+        let calls = Cell::new(0);
+        // The boundary is the imaginary axis
+        let estimate = |z: SimdComplex<2>| {
+            calls.set(calls.get() + 1);
+            z.re.abs()
+        };
+
+        let far = SimdComplex::splat(5.0 * pixel_size, 0.0);
+        assert_eq!(
+            coloring.boundary_array(far, pixel_size, estimate),
+            [Rgb(255, 255, 255); 2]
+        );
+        assert_eq!(calls.get(), 1);
+
+        // The subsamples are at -0.15 and 0.35 pixels from the axis, so the coverage is 0.75
+        let near = SimdComplex::splat(0.1 * pixel_size, 0.0);
+        assert_eq!(
+            coloring.boundary_array(near, pixel_size, estimate),
+            [grayscale(64); 2]
+        );
+        assert_eq!(calls.get(), 1 + 1 + 4);
+    }
+
+    #[test]
+    fn palette_colors_grow_darker_away_from_the_boundary() {
+        let coloring = DistanceColoring::DEFAULT;
+        let palette = &palettes::CYAN;
+        let distances = SimdDouble::from_array([0.0, 0.1, 0.2, 0.4, 1e6]);
+        let colors = coloring.palette_color_array(palette, distances, 0.1);
+        assert_eq!(colors[0], palette.color(MAX_ITERS));
+        assert_eq!(colors[1], palette.color(MAX_ITERS));
+        assert_eq!(colors[2], palette.color(MAX_ITERS - 16));
+        assert_eq!(colors[3], palette.color(MAX_ITERS - 32));
+        assert_eq!(colors[4], palette.color(0));
+    }
+
+    #[test]
+    fn flat_relief_is_lit_evenly() {
+        let lighting = Lighting {
+            height: 0.0,
+            ..Lighting::DEFAULT
+        };
+        let color = Rgb(200, 100, 0);
+        let z = Complex::new(1e4, 0.0);
+        let shade = |dz| lighting.shade(color, 10, z, dz);
+        assert_eq!(shade(Complex::new(1.0, 0.0)), shade(Complex::new(0.0, 1.0)));
+
+        let overhead = Lighting {
+            elevation: std::f64::consts::FRAC_PI_2,
+            specular: 0.0,
+            ..lighting
+        };
+        assert_eq!(overhead.shade(color, 10, z, Complex::new(1.0, 0.0)), color);
+    }
+
+    #[test]
+    fn slopes_facing_the_light_are_brighter() {
+        let lighting = Lighting {
+            azimuth: 0.0,
+            specular: 0.0,
+            ..Lighting::DEFAULT
+        };
+        let (towards, _) = lighting.intensity(Complex::new(1.0, 0.0));
+        let (across, _) = lighting.intensity(Complex::new(0.0, 1.0));
+        let (away, _) = lighting.intensity(Complex::new(-1.0, 0.0));
+        assert!(towards > across && across > away);
+
+        let shiny = Lighting {
+            specular: 1.0,
+            ..lighting
+        };
+        let (_, towards) = shiny.intensity(Complex::new(1.0, 0.0));
+        let (_, away) = shiny.intensity(Complex::new(-1.0, 0.0));
+        assert!(towards > away);
+    }
+
+    #[test]
+    fn shades_apply_to_any_palette_color() {
+        let lighting = Lighting::DEFAULT;
+        let z = Complex::new(1e4, 0.0);
+        let dz = Complex::new(1.0, 1.0);
+        let shade = lighting.shade_of(10, z, dz);
+        assert_ne!(shade, Shade::NONE);
+        assert_eq!(lighting.shade_of(MAX_ITERS, z, dz), Shade::NONE);
+        for (_, palette) in palettes::all() {
+            for color in palette.colors() {
+                assert_eq!(shade.apply(*color), lighting.shade(*color, 10, z, dz));
+                assert_eq!(Shade::NONE.apply(*color), *color);
+            }
+        }
+    }
+
+    #[test]
+    fn interior_is_not_shaded() {
+        let lighting = Lighting::DEFAULT;
+        let colors = [Rgb(10, 20, 30); 2];
+        let z = SimdComplex::splat(0.5, 0.5);
+        let dz = SimdComplex::splat(1.0, 0.0);
+        let shaded = lighting.shade_array(colors, [MAX_ITERS, 10], z, dz);
+        assert_eq!(shaded[0], colors[0]);
+        assert_ne!(shaded[1], colors[1]);
+        let flat = lighting.shade(
+            colors[0],
+            10,
+            Complex::new(0.5, 0.5),
+            Complex::new(0.0, 0.0),
+        );
+        assert_eq!(flat, colors[0]);
+    }
+
+    #[test]
+    fn trap_palette_colors_follow_distance() {
+        let coloring = TrapColoring { image: None };
+        let palette = &palettes::CYAN;
+        let trap = OrbitTrap::DEFAULT;
+        assert_eq!(coloring.palette_color(palette, trap, 0.0), palette.color(0));
+        assert_eq!(coloring.palette_index(trap, f64::INFINITY), MAX_ITERS);
+        assert_eq!(coloring.image_color(trap, 0.0, Complex::default()), None);
+        assert_eq!(
+            coloring.palette_color(palette, trap, trap.size),
+            palette.color(MAX_ITERS / 2 + 1)
+        );
+        assert_eq!(
+            coloring.palette_color(palette, trap, f64::INFINITY),
+            palette.color(MAX_ITERS)
+        );
+    }
+
+    #[test]
+    fn image_trap_shows_the_trapped_pixel() {
+        let colors = [
+            Rgb(255, 0, 0),
             Rgb(0, 255, 0),
-            Rgb(0, 0, 0),
-        ])
-    });
+            Rgb(0, 0, 255),
+            Rgb(255, 255, 0),
+        ];
+        let coloring = TrapColoring {
+            image: Some(Image {
+                width: 2,
+                height: 2,
+                pixels: colors.to_vec(),
+            }),
+        };
+        let palette = &palettes::CYAN;
+        let trap = OrbitTrap {
+            shape: TrapShape::Image,
+            center: Complex::new(5.0, 5.0),
+            size: 2.0,
+        };
+        let color = |re, im| coloring.color(palette, trap, 0.0, Complex::new(re, im));
+        assert_eq!(color(-0.5, 0.5), colors[0]);
+        assert_eq!(color(0.5, 0.5), colors[1]);
+        assert_eq!(color(-0.5, -0.5), colors[2]);
+        assert_eq!(color(1.0, -1.0), colors[3]);
+        assert_eq!(
+            coloring.color(palette, trap, 0.1, Complex::new(0.0, 0.0)),
+            coloring.palette_color(palette, trap, 0.1)
+        );
+    }
+
+    #[test]
+    fn rotated_palettes_keep_the_interior_color() {
+        let palette = &palettes::RAINBOW_LIGHT;
+        let rotated = palette.rotated(10);
+        assert_eq!(rotated.color(0), palette.color(10));
+        assert_eq!(rotated.color(MAX_ITERS - 10), palette.color(0));
+        assert_eq!(rotated.color(MAX_ITERS), palette.color(MAX_ITERS));
+        assert_eq!(palette.rotated(0).colors(), palette.colors());
+        assert_eq!(
+            palette.rotated(MAX_ITERS - 1).rotated(1).colors(),
+            palette.colors()
+        );
+    }
+
+    #[test]
+    fn cosine_palettes_follow_the_formula() {
+        let rainbow = Palette::from_cosine(Cosine::DEFAULT);
+        assert_eq!(rainbow.color(0), Rgb(255, 64, 64));
+        assert_eq!(rainbow.color(0), rainbow.color(MAX_ITERS));
+
+        let clamped = Cosine {
+            offset: [1.5, 0.5, -0.5],
+            amplitude: [0.0; 3],
+            ..Cosine::DEFAULT
+        };
+        let palette = Palette::from_cosine(clamped);
+        assert!(palette
+            .colors()
+            .iter()
+            .all(|&color| color == Rgb(255, 128, 0)));
+    }
+
+    #[test]
+    fn colormaps_match_their_reference_colors() {
+        // The turbo fit is looser at the ends, so only the uniform colormaps are checked.
+        // The polynomial fits are within a few units of the reference colors at the ends.
+        let references = [
+            (Colormap::Viridis, Rgb(68, 1, 84), Rgb(253, 231, 37)),
+            (Colormap::Magma, Rgb(0, 0, 4), Rgb(252, 253, 191)),
+            (Colormap::Inferno, Rgb(0, 0, 4), Rgb(252, 255, 164)),
+            (Colormap::Plasma, Rgb(13, 8, 135), Rgb(240, 249, 33)),
+            (Colormap::Cividis, Rgb(0, 32, 76), Rgb(255, 233, 69)),
+        ];
+        let close = |lhs: Rgb, rhs: Rgb| {
+            [(lhs.0, rhs.0), (lhs.1, rhs.1), (lhs.2, rhs.2)]
+                .iter()
+                .all(|&(a, b)| a.abs_diff(b) <= 5)
+        };
+        for (colormap, first, last) in references {
+            let palette = Palette::from_colormap(colormap);
+            assert!(close(palette.color(0), first), "{}", colormap.name());
+            assert!(close(palette.color(MAX_ITERS), last), "{}", colormap.name());
+        }
+    }
+
+    #[test]
+    fn uniform_colormaps_grow_lighter() {
+        for colormap in Colormap::ALL {
+            if colormap == Colormap::Turbo {
+                continue;
+            }
+            let lightness = Palette::from_colormap(colormap)
+                .colors()
+                .iter()
+                .map(|&color| to_oklab(color)[0])
+                .collect::<Vec<_>>();
+            assert!(
+                lightness.windows(2).all(|w| w[1] > w[0] - 0.005),
+                "{}",
+                colormap.name()
+            );
+            assert!(lightness[MAX_ITERS as usize] - lightness[0] > 0.6);
+        }
+    }
 }
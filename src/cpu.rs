@@ -0,0 +1,69 @@
+use std::fmt::{self, Display};
+
+/// Instruction set extensions that rule kernels can be compiled for.
+/// Levels are ordered, so a higher level implies support for all lower ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SimdLevel {
+    /// No vector extensions assumed beyond the build target's baseline.
+    Scalar,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Sse2,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Avx2,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Avx512,
+}
+
+impl SimdLevel {
+    /// Detects the best level supported by the CPU we are currently running on.
+    pub fn detect() -> Self {
+        Self::supported().into_iter().max().unwrap_or(Self::Scalar)
+    }
+
+    /// Returns all levels supported by the current CPU, in ascending order.
+    pub fn supported() -> Vec<Self> {
+        let mut levels = vec![Self::Scalar];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("sse2") {
+                levels.push(Self::Sse2);
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                levels.push(Self::Avx2);
+            }
+            if is_x86_feature_detected!("avx512f") {
+                levels.push(Self::Avx512);
+            }
+        }
+        levels
+    }
+
+    /// Number of `f64` values that fit in a single native vector register at this level,
+    /// which is also the number of lanes that render kernels run with.
+    pub fn register_lanes(self) -> usize {
+        match self {
+            Self::Scalar => 1,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Sse2 => 2,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Avx2 => 4,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Avx512 => 8,
+        }
+    }
+}
+
+impl Display for SimdLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Scalar => "Scalar",
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Sse2 => "SSE2",
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Avx2 => "AVX2",
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Self::Avx512 => "AVX-512",
+        };
+        f.write_str(name)
+    }
+}
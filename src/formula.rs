@@ -0,0 +1,1045 @@
+//! Formula language for custom fractals, parsed at runtime.
+//!
+//! A formula is a list of statements separated by `;` or new lines:
+//! * `z = <expr>` assigns a variable on every iteration. Statements run in order
+//!   and new variables can be introduced for intermediate results.
+//! * `init <var> = <expr>` assigns a variable once, before the first iteration.
+//! * `bailout <condition>` stops iterating a pixel once the condition holds.
+//!   It defaults to `|z| >= 2`. Pixels whose `z` becomes NaN always bail out.
+//!
+//! The built-in variables are `z` (initially 0), `c` (initially `pixel`),
+//! `seed` (controlled by the user) and `pixel` (the point in the Complex plane).
+//! For example, `init z = pixel; init c = seed; z = z^2 + c` is the Julia set.
+//!
+//! Expressions support `+ - * / ^`, `|x|`, numbers like `2`, `0.5`, `1e-3` and `2i`,
+//! the imaginary unit `i` and the functions `sin`, `cos`, `tan`, `sinh`, `cosh`,
+//! `exp`, `ln`, `sqrt`, `conj`, `abs`, `arg`, `re` and `im`.
+//! Conditions compare the real parts of two expressions with `< <= > >=`
+//! and can be combined with `and`/`or`.
+
+use crate::{simd::SimdComplex, utils::Complex};
+use nalgebra::ComplexField;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    simd::{cmp::SimdPartialOrd, num::SimdFloat, Mask},
+};
+
+/// Maximum number of variables, including the built-in ones.
+pub const MAX_VARIABLES: usize = 16;
+
+/// Values of all the variables of a formula, indexed by slot.
+pub type Variables<const N: usize> = [SimdComplex<N>; MAX_VARIABLES];
+
+const BUILTIN_VARIABLES: [&str; 4] = ["z", "c", "seed", "pixel"];
+const Z_SLOT: usize = 0;
+const C_SLOT: usize = 1;
+const SEED_SLOT: usize = 2;
+const PIXEL_SLOT: usize = 3;
+const ESCAPE_RADIUS_SQUARED: f64 = 4.0;
+
+/// A formula compiled into expression trees that run on SIMD lanes.
+#[derive(Debug, Clone)]
+pub struct Formula {
+    init: Vec<Assignment>,
+    step: Vec<Assignment>,
+    bailout: Condition,
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Self, FormulaError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let program = Parser::new(tokens).parse_program()?;
+        Compiler::default().compile(program)
+    }
+
+    pub fn initial_variables<const N: usize>(
+        &self,
+        pixel: SimdComplex<N>,
+        seed: SimdComplex<N>,
+    ) -> Variables<N> {
+        let mut vars = [SimdComplex::default(); MAX_VARIABLES];
+        vars[C_SLOT] = pixel;
+        vars[SEED_SLOT] = seed;
+        vars[PIXEL_SLOT] = pixel;
+        for assignment in &self.init {
+            assignment.execute(&mut vars);
+        }
+        vars
+    }
+
+    /// Returns the lanes that should stop iterating.
+    pub fn bailout<const N: usize>(&self, vars: &Variables<N>) -> Mask<i64, N> {
+        let z = vars[Z_SLOT];
+        self.bailout.eval(vars) | z.re.is_nan() | z.im.is_nan()
+    }
+
+    pub fn step<const N: usize>(&self, vars: &mut Variables<N>) {
+        for assignment in &self.step {
+            assignment.execute(vars);
+        }
+    }
+}
+
+/// Error found while parsing a formula.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaError {
+    /// Byte offset in the source where the error was found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl FormulaError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Formats the error together with the offending source line and a caret under the error.
+    pub fn pretty(&self, source: &str) -> String {
+        let position = self.position.min(source.len());
+        let line_start = source[..position].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[position..]
+            .find('\n')
+            .map_or(source.len(), |i| position + i);
+        let line_number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..position].chars().count();
+        format!(
+            "error at line {}, column {}: {}\n{}\n{}^",
+            line_number,
+            column + 1,
+            self.message,
+            &source[line_start..line_end],
+            " ".repeat(column)
+        )
+    }
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at byte {})", self.message, self.position)
+    }
+}
+
+impl Error for FormulaError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Imaginary(f64),
+    Identifier(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LeftParen,
+    RightParen,
+    Comma,
+    Pipe,
+    Assign,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Separator,
+    End,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Number(x) => write!(f, "number {}", x),
+            TokenKind::Imaginary(x) => write!(f, "number {}i", x),
+            TokenKind::Identifier(name) => write!(f, "'{}'", name),
+            TokenKind::Plus => f.write_str("'+'"),
+            TokenKind::Minus => f.write_str("'-'"),
+            TokenKind::Star => f.write_str("'*'"),
+            TokenKind::Slash => f.write_str("'/'"),
+            TokenKind::Caret => f.write_str("'^'"),
+            TokenKind::LeftParen => f.write_str("'('"),
+            TokenKind::RightParen => f.write_str("')'"),
+            TokenKind::Comma => f.write_str("','"),
+            TokenKind::Pipe => f.write_str("'|'"),
+            TokenKind::Assign => f.write_str("'='"),
+            TokenKind::Less => f.write_str("'<'"),
+            TokenKind::LessEqual => f.write_str("'<='"),
+            TokenKind::Greater => f.write_str("'>'"),
+            TokenKind::GreaterEqual => f.write_str("'>='"),
+            TokenKind::Separator => f.write_str("end of statement"),
+            TokenKind::End => f.write_str("end of formula"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, FormulaError> {
+        let mut tokens = Vec::new();
+        while let Some(c) = self.peek() {
+            let start = self.position;
+            let kind = match c {
+                '\n' | ';' => {
+                    self.bump();
+                    TokenKind::Separator
+                }
+                c if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                c if c.is_ascii_digit() || c == '.' => self.number()?,
+                c if c.is_alphabetic() || c == '_' => {
+                    TokenKind::Identifier(self.take_while(|c| c.is_alphanumeric() || c == '_'))
+                }
+                _ => {
+                    self.bump();
+                    match (c, self.peek()) {
+                        ('<', Some('=')) => {
+                            self.bump();
+                            TokenKind::LessEqual
+                        }
+                        ('>', Some('=')) => {
+                            self.bump();
+                            TokenKind::GreaterEqual
+                        }
+                        ('<', _) => TokenKind::Less,
+                        ('>', _) => TokenKind::Greater,
+                        ('+', _) => TokenKind::Plus,
+                        ('-', _) => TokenKind::Minus,
+                        ('*', _) => TokenKind::Star,
+                        ('/', _) => TokenKind::Slash,
+                        ('^', _) => TokenKind::Caret,
+                        ('(', _) => TokenKind::LeftParen,
+                        (')', _) => TokenKind::RightParen,
+                        (',', _) => TokenKind::Comma,
+                        ('|', _) => TokenKind::Pipe,
+                        ('=', _) => TokenKind::Assign,
+                        _ => {
+                            return Err(FormulaError::new(
+                                start,
+                                format!("unexpected character '{}'", c),
+                            ))
+                        }
+                    }
+                }
+            };
+            tokens.push(Token {
+                kind,
+                position: start,
+            });
+        }
+        tokens.push(Token {
+            kind: TokenKind::End,
+            position: self.source.len(),
+        });
+        Ok(tokens)
+    }
+
+    fn number(&mut self) -> Result<TokenKind, FormulaError> {
+        let start = self.position;
+        let mut text = self.take_while(|c| c.is_ascii_digit() || c == '.');
+        // Only treat `e` as an exponent when digits follow, so that `2e` stays an error
+        if let Some('e' | 'E') = self.peek() {
+            let rest = &self.source[self.position + 1..];
+            let unsigned = rest.trim_start_matches(['+', '-']);
+            let sign_len = (rest.len() - unsigned.len()).min(1);
+            let digit_count = unsigned.chars().take_while(char::is_ascii_digit).count();
+            if digit_count > 0 && rest.len() - unsigned.len() <= 1 {
+                let end = self.position + 1 + sign_len + digit_count;
+                text.push_str(&self.source[self.position..end]);
+                self.position = end;
+            }
+        }
+
+        let value: f64 = text
+            .parse()
+            .map_err(|_| FormulaError::new(start, format!("invalid number '{}'", text)))?;
+
+        // A number directly followed by a standalone `i` is imaginary, like `2i`
+        let mut rest = self.source[self.position..].chars();
+        if rest.next() == Some('i') && !rest.next().is_some_and(|c| c.is_alphanumeric() || c == '_')
+        {
+            self.bump();
+            return Ok(TokenKind::Imaginary(value));
+        }
+        Ok(TokenKind::Number(value))
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
+        }
+        self.source[start..self.position].to_string()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.position += c.len_utf8();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Exp,
+    Ln,
+    Sqrt,
+    Conj,
+    Abs,
+    Arg,
+    Re,
+    Im,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "sqrt" => Function::Sqrt,
+            "conj" => Function::Conj,
+            "abs" => Function::Abs,
+            "arg" => Function::Arg,
+            "re" => Function::Re,
+            "im" => Function::Im,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn apply<const N: usize>(self, z: SimdComplex<N>) -> SimdComplex<N> {
+        let real = |re| SimdComplex {
+            re,
+            im: Default::default(),
+        };
+        match self {
+            Function::Sin => z.sin(),
+            Function::Cos => z.cos(),
+            Function::Tan => z.tan(),
+            Function::Sinh => z.sinh(),
+            Function::Cosh => z.cosh(),
+            Function::Exp => z.exp(),
+            Function::Ln => z.ln(),
+            Function::Sqrt => z.sqrt(),
+            Function::Conj => z.conj(),
+            Function::Abs => real(z.abs()),
+            Function::Arg => real(z.arg()),
+            Function::Re => real(z.re),
+            Function::Im => real(z.im),
+        }
+    }
+
+    fn apply_scalar(self, z: Complex) -> Complex {
+        match self {
+            Function::Sin => z.sin(),
+            Function::Cos => z.cos(),
+            Function::Tan => z.tan(),
+            Function::Sinh => z.sinh(),
+            Function::Cosh => z.cosh(),
+            Function::Exp => z.exp(),
+            Function::Ln => z.ln(),
+            Function::Sqrt => z.sqrt(),
+            Function::Conj => z.conj(),
+            Function::Abs => Complex::new(z.modulus(), 0.0),
+            Function::Arg => Complex::new(z.argument(), 0.0),
+            Function::Re => Complex::new(z.re, 0.0),
+            Function::Im => Complex::new(z.im, 0.0),
+        }
+    }
+}
+
+/// Expression as written in the source, with variables not yet resolved.
+#[derive(Debug, Clone, PartialEq)]
+enum Ast {
+    Constant(Complex),
+    Variable(String, usize),
+    Negate(Box<Ast>),
+    Binary(BinaryOp, Box<Ast>, Box<Ast>),
+    Call(Function, Box<Ast>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConditionAst {
+    Compare(CompareOp, Ast, Ast),
+    And(Box<ConditionAst>, Box<ConditionAst>),
+    Or(Box<ConditionAst>, Box<ConditionAst>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AssignmentAst {
+    target: String,
+    position: usize,
+    value: Ast,
+}
+
+#[derive(Debug, Default)]
+struct Program {
+    init: Vec<AssignmentAst>,
+    step: Vec<AssignmentAst>,
+    bailout: Option<ConditionAst>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens, index: 0 }
+    }
+
+    fn parse_program(mut self) -> Result<Program, FormulaError> {
+        let mut program = Program::default();
+        loop {
+            while self.peek().kind == TokenKind::Separator {
+                self.advance();
+            }
+            let token = self.peek().clone();
+            match &token.kind {
+                TokenKind::End => break,
+                TokenKind::Identifier(keyword) if keyword == "init" => {
+                    self.advance();
+                    program.init.push(self.parse_assignment()?);
+                }
+                TokenKind::Identifier(keyword) if keyword == "bailout" => {
+                    self.advance();
+                    if program.bailout.is_some() {
+                        return Err(FormulaError::new(
+                            token.position,
+                            "a formula can only have one bailout condition",
+                        ));
+                    }
+                    program.bailout = Some(self.parse_condition()?);
+                }
+                _ => program.step.push(self.parse_assignment()?),
+            }
+            let token = self.peek();
+            if !matches!(token.kind, TokenKind::Separator | TokenKind::End) {
+                return Err(self.unexpected("';' or a new line"));
+            }
+        }
+        if program.step.is_empty() {
+            return Err(FormulaError::new(
+                self.peek().position,
+                "expected at least one iteration statement, like 'z = z^2 + c'",
+            ));
+        }
+        Ok(program)
+    }
+
+    fn parse_assignment(&mut self) -> Result<AssignmentAst, FormulaError> {
+        let token = self.peek().clone();
+        let TokenKind::Identifier(target) = token.kind else {
+            return Err(self.unexpected("a variable name"));
+        };
+        self.advance();
+        self.expect(TokenKind::Assign, "'='")?;
+        Ok(AssignmentAst {
+            target,
+            position: token.position,
+            value: self.parse_expression()?,
+        })
+    }
+
+    fn parse_condition(&mut self) -> Result<ConditionAst, FormulaError> {
+        let mut lhs = self.parse_and_condition()?;
+        while self.eat_keyword("or") {
+            lhs = ConditionAst::Or(Box::new(lhs), Box::new(self.parse_and_condition()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and_condition(&mut self) -> Result<ConditionAst, FormulaError> {
+        let mut lhs = self.parse_comparison()?;
+        while self.eat_keyword("and") {
+            lhs = ConditionAst::And(Box::new(lhs), Box::new(self.parse_comparison()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_comparison(&mut self) -> Result<ConditionAst, FormulaError> {
+        let lhs = self.parse_expression()?;
+        let op = match self.peek().kind {
+            TokenKind::Less => CompareOp::Less,
+            TokenKind::LessEqual => CompareOp::LessEqual,
+            TokenKind::Greater => CompareOp::Greater,
+            TokenKind::GreaterEqual => CompareOp::GreaterEqual,
+            _ => return Err(self.unexpected("a comparison operator ('<', '<=', '>' or '>=')")),
+        };
+        self.advance();
+        Ok(ConditionAst::Compare(op, lhs, self.parse_expression()?))
+    }
+
+    fn parse_expression(&mut self) -> Result<Ast, FormulaError> {
+        let mut lhs = self.parse_term()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Plus => BinaryOp::Add,
+                TokenKind::Minus => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Ast::Binary(op, Box::new(lhs), Box::new(self.parse_term()?));
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Ast, FormulaError> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Star => BinaryOp::Mul,
+                TokenKind::Slash => BinaryOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Ast::Binary(op, Box::new(lhs), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Ast, FormulaError> {
+        if self.peek().kind == TokenKind::Minus {
+            self.advance();
+            return Ok(Ast::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_power()
+    }
+
+    fn parse_power(&mut self) -> Result<Ast, FormulaError> {
+        let base = self.parse_primary()?;
+        if self.peek().kind != TokenKind::Caret {
+            return Ok(base);
+        }
+        self.advance();
+        // Right associative, and allows negative exponents like `z^-2`
+        let exponent = self.parse_unary()?;
+        Ok(Ast::Binary(
+            BinaryOp::Pow,
+            Box::new(base),
+            Box::new(exponent),
+        ))
+    }
+
+    fn parse_primary(&mut self) -> Result<Ast, FormulaError> {
+        let token = self.peek().clone();
+        match token.kind {
+            TokenKind::Number(x) => {
+                self.advance();
+                Ok(Ast::Constant(Complex::new(x, 0.0)))
+            }
+            TokenKind::Imaginary(x) => {
+                self.advance();
+                Ok(Ast::Constant(Complex::new(0.0, x)))
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+                let inner = self.parse_expression()?;
+                self.expect(TokenKind::RightParen, "')'")?;
+                Ok(inner)
+            }
+            TokenKind::Pipe => {
+                self.advance();
+                let inner = self.parse_expression()?;
+                self.expect(TokenKind::Pipe, "closing '|'")?;
+                Ok(Ast::Call(Function::Abs, Box::new(inner)))
+            }
+            TokenKind::Identifier(name) => {
+                self.advance();
+                if self.peek().kind != TokenKind::LeftParen {
+                    return Ok(match name.as_str() {
+                        "i" => Ast::Constant(Complex::new(0.0, 1.0)),
+                        _ => Ast::Variable(name, token.position),
+                    });
+                }
+                let function = Function::from_name(&name).ok_or_else(|| {
+                    FormulaError::new(token.position, format!("unknown function '{}'", name))
+                })?;
+                self.advance();
+                let argument = self.parse_expression()?;
+                if self.peek().kind == TokenKind::Comma {
+                    return Err(FormulaError::new(
+                        self.peek().position,
+                        format!("function '{}' takes a single argument", name),
+                    ));
+                }
+                self.expect(TokenKind::RightParen, "')'")?;
+                Ok(Ast::Call(function, Box::new(argument)))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(&self.peek().kind, TokenKind::Identifier(name) if name == keyword);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect(&mut self, kind: TokenKind, description: &str) -> Result<(), FormulaError> {
+        if self.peek().kind != kind {
+            return Err(self.unexpected(description));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: &str) -> FormulaError {
+        let token = self.peek();
+        FormulaError::new(
+            token.position,
+            format!("expected {}, found {}", expected, token.kind),
+        )
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index]
+    }
+
+    fn advance(&mut self) {
+        if self.index + 1 < self.tokens.len() {
+            self.index += 1;
+        }
+    }
+}
+
+/// Expression tree with resolved variable slots and specialized powers.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Constant(Complex),
+    Variable(usize),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    PowI(Box<Expr>, u32),
+    PowF(Box<Expr>, f64),
+    Call(Function, Box<Expr>),
+}
+
+impl Expr {
+    fn eval<const N: usize>(&self, vars: &Variables<N>) -> SimdComplex<N> {
+        match self {
+            Expr::Constant(c) => SimdComplex::from_complex(*c),
+            Expr::Variable(slot) => vars[*slot],
+            Expr::Negate(inner) => -inner.eval(vars),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(vars), rhs.eval(vars));
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    BinaryOp::Mul => lhs * rhs,
+                    BinaryOp::Div => lhs / rhs,
+                    BinaryOp::Pow => lhs.powc(rhs),
+                }
+            }
+            Expr::PowI(base, exp) => base.eval(vars).powi(*exp),
+            Expr::PowF(base, exp) => base.eval(vars).powf(*exp),
+            Expr::Call(function, argument) => function.apply(argument.eval(vars)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// Default bailout: `|z| >= 2`, computed without a square root.
+    Escape,
+    Compare(CompareOp, Expr, Expr),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    fn eval<const N: usize>(&self, vars: &Variables<N>) -> Mask<i64, N> {
+        match self {
+            Condition::Escape => !vars[Z_SLOT]
+                .norm_squared()
+                .simd_lt(SimdComplex::<N>::splat(ESCAPE_RADIUS_SQUARED, 0.0).re),
+            Condition::Compare(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(vars).re, rhs.eval(vars).re);
+                match op {
+                    CompareOp::Less => lhs.simd_lt(rhs),
+                    CompareOp::LessEqual => lhs.simd_le(rhs),
+                    CompareOp::Greater => lhs.simd_gt(rhs),
+                    CompareOp::GreaterEqual => lhs.simd_ge(rhs),
+                }
+            }
+            Condition::And(lhs, rhs) => lhs.eval(vars) & rhs.eval(vars),
+            Condition::Or(lhs, rhs) => lhs.eval(vars) | rhs.eval(vars),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Assignment {
+    slot: usize,
+    value: Expr,
+}
+
+impl Assignment {
+    fn execute<const N: usize>(&self, vars: &mut Variables<N>) {
+        vars[self.slot] = self.value.eval(vars);
+    }
+}
+
+/// Resolves variables to slots, checks that they are assigned before being read,
+/// folds constants and picks the fastest power implementation.
+struct Compiler {
+    variables: Vec<String>,
+    assigned: Vec<bool>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            variables: BUILTIN_VARIABLES.map(String::from).to_vec(),
+            assigned: vec![true; BUILTIN_VARIABLES.len()],
+        }
+    }
+}
+
+impl Compiler {
+    fn compile(mut self, program: Program) -> Result<Formula, FormulaError> {
+        // Declare all targets first, so that reading a variable before its assignment
+        // is reported as such rather than as an unknown variable
+        for assignment in program.init.iter().chain(&program.step) {
+            self.declare(assignment)?;
+        }
+
+        let init = program
+            .init
+            .into_iter()
+            .map(|assignment| self.compile_assignment(assignment))
+            .collect::<Result<_, _>>()?;
+        // The bailout condition is evaluated before the first step
+        let bailout = match program.bailout {
+            Some(condition) => self.compile_condition(condition)?,
+            None => Condition::Escape,
+        };
+        let step = program
+            .step
+            .into_iter()
+            .map(|assignment| self.compile_assignment(assignment))
+            .collect::<Result<_, _>>()?;
+        Ok(Formula {
+            init,
+            step,
+            bailout,
+        })
+    }
+
+    fn declare(&mut self, assignment: &AssignmentAst) -> Result<(), FormulaError> {
+        let target = assignment.target.as_str();
+        if target == "seed" || target == "pixel" {
+            return Err(FormulaError::new(
+                assignment.position,
+                format!("cannot assign to the read-only variable '{}'", target),
+            ));
+        }
+        if target == "i" || Function::from_name(target).is_some() {
+            return Err(FormulaError::new(
+                assignment.position,
+                format!("'{}' is reserved and cannot be assigned", target),
+            ));
+        }
+        if self.slot(target).is_none() {
+            if self.variables.len() == MAX_VARIABLES {
+                return Err(FormulaError::new(
+                    assignment.position,
+                    format!("a formula can use at most {} variables", MAX_VARIABLES),
+                ));
+            }
+            self.variables.push(assignment.target.clone());
+            self.assigned.push(false);
+        }
+        Ok(())
+    }
+
+    fn compile_assignment(
+        &mut self,
+        assignment: AssignmentAst,
+    ) -> Result<Assignment, FormulaError> {
+        let value = self.compile_expression(assignment.value)?;
+        let slot = self
+            .slot(&assignment.target)
+            .expect("assignment targets are declared before compiling");
+        self.assigned[slot] = true;
+        Ok(Assignment { slot, value })
+    }
+
+    fn compile_condition(&self, condition: ConditionAst) -> Result<Condition, FormulaError> {
+        Ok(match condition {
+            ConditionAst::Compare(op, lhs, rhs) => Condition::Compare(
+                op,
+                self.compile_expression(lhs)?,
+                self.compile_expression(rhs)?,
+            ),
+            ConditionAst::And(lhs, rhs) => Condition::And(
+                Box::new(self.compile_condition(*lhs)?),
+                Box::new(self.compile_condition(*rhs)?),
+            ),
+            ConditionAst::Or(lhs, rhs) => Condition::Or(
+                Box::new(self.compile_condition(*lhs)?),
+                Box::new(self.compile_condition(*rhs)?),
+            ),
+        })
+    }
+
+    fn compile_expression(&self, ast: Ast) -> Result<Expr, FormulaError> {
+        Ok(match ast {
+            Ast::Constant(c) => Expr::Constant(c),
+            Ast::Variable(name, position) => match self.slot(&name) {
+                Some(slot) if self.assigned[slot] => Expr::Variable(slot),
+                Some(_) => {
+                    return Err(FormulaError::new(
+                        position,
+                        format!("variable '{}' is used before being assigned", name),
+                    ))
+                }
+                None => {
+                    return Err(FormulaError::new(
+                        position,
+                        format!("unknown variable '{}'", name),
+                    ))
+                }
+            },
+            Ast::Negate(inner) => match self.compile_expression(*inner)? {
+                Expr::Constant(c) => Expr::Constant(-c),
+                inner => Expr::Negate(Box::new(inner)),
+            },
+            Ast::Call(function, argument) => match self.compile_expression(*argument)? {
+                Expr::Constant(c) => Expr::Constant(function.apply_scalar(c)),
+                argument => Expr::Call(function, Box::new(argument)),
+            },
+            Ast::Binary(op, lhs, rhs) => {
+                let lhs = self.compile_expression(*lhs)?;
+                let rhs = self.compile_expression(*rhs)?;
+                Self::compile_binary(op, lhs, rhs)
+            }
+        })
+    }
+
+    fn compile_binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        match (op, lhs, rhs) {
+            (op, Expr::Constant(lhs), Expr::Constant(rhs)) => Expr::Constant(match op {
+                BinaryOp::Add => lhs + rhs,
+                BinaryOp::Sub => lhs - rhs,
+                BinaryOp::Mul => lhs * rhs,
+                BinaryOp::Div => lhs / rhs,
+                BinaryOp::Pow => lhs.powc(rhs),
+            }),
+            (BinaryOp::Pow, base, Expr::Constant(exp)) if exp.im == 0.0 => {
+                if exp.re >= 0.0 && exp.re <= u32::MAX as f64 && exp.re.fract() == 0.0 {
+                    Expr::PowI(Box::new(base), exp.re as u32)
+                } else {
+                    Expr::PowF(Box::new(base), exp.re)
+                }
+            }
+            (op, lhs, rhs) => Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.variables.iter().position(|v| v == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        formula::{Formula, FormulaError},
+        rules::{self, Bailout},
+        simd::SimdComplex,
+    };
+    use itertools::Itertools;
+
+    #[test]
+    fn mandelbrot_formula_matches_rule() {
+        let formula = parse("z = z^2 + c");
+        for c in sample_points() {
+            assert_eq!(
+                rules::formula(&formula, c, seed()),
+                rules::mandelbrot(c, Bailout::DEFAULT)
+            );
+        }
+    }
+
+    #[test]
+    fn julia_formula_matches_rule() {
+        let formula = parse("init z = pixel\ninit c = seed\nz = z*z + c; bailout |z| >= 2");
+        for z in sample_points() {
+            assert_eq!(
+                rules::formula(&formula, z, seed()),
+                rules::julia(z, seed(), Bailout::DEFAULT)
+            );
+        }
+    }
+
+    #[test]
+    fn burning_ship_formula_matches_rule() {
+        let formula = parse("w = abs(re(z)) + i*abs(im(z)); z = w^2 + c");
+        for c in sample_points() {
+            assert_eq!(
+                rules::formula(&formula, c, seed()),
+                rules::burning_ship(c, Bailout::DEFAULT)
+            );
+        }
+    }
+
+    #[test]
+    fn bailout_conditions_can_be_combined() {
+        let formula =
+            parse("z = z^2 + c; bailout re(z) > 2 or re(z) < -2 or |im(z)| >= 2 and 1 < 2");
+        let counts = rules::formula(&formula, SimdComplex::<8>::splat(3.0, 0.0), seed());
+        assert_eq!(counts, [1; 8]);
+    }
+
+    #[test]
+    fn constant_subexpressions_are_folded() {
+        let formula = parse("z = z^(1 + 1) + c * (2 - 1)");
+        for c in sample_points() {
+            assert_eq!(
+                rules::formula(&formula, c, seed()),
+                rules::mandelbrot(c, Bailout::DEFAULT)
+            );
+        }
+    }
+
+    #[test]
+    fn errors_point_to_the_offending_token() {
+        check_error("z = z^ + c", 7, "expected an expression, found '+'");
+        check_error("z = foo(z) + c", 4, "unknown function 'foo'");
+        check_error("z = z^2 + q", 10, "unknown variable 'q'");
+        check_error(
+            "pixel = z",
+            0,
+            "cannot assign to the read-only variable 'pixel'",
+        );
+        check_error("z = (z + c", 10, "expected ')', found end of formula");
+        check_error("z = z # c", 6, "unexpected character '#'");
+        check_error(
+            "z = z^2 + c; bailout |z| 4",
+            25,
+            "expected a comparison operator",
+        );
+        check_error(
+            "z = t + c; t = z",
+            4,
+            "variable 't' is used before being assigned",
+        );
+        check_error(
+            "z = z^2 + c\nz = z c",
+            18,
+            "expected ';' or a new line, found 'c'",
+        );
+        check_error(
+            "bailout |z| > 2",
+            15,
+            "expected at least one iteration statement",
+        );
+        check_error("z = sin(z, c)", 9, "function 'sin' takes a single argument");
+    }
+
+    #[test]
+    fn pretty_errors_show_the_source_line() {
+        let source = "init z = pixel\nz = z^ + c";
+        let error = Formula::parse(source).unwrap_err();
+        assert_eq!(
+            error.pretty(source),
+            "error at line 2, column 8: expected an expression, found '+'\nz = z^ + c\n       ^"
+        );
+    }
+
+    #[test]
+    fn numbers_are_parsed() {
+        let formula = parse("init z = 1.5e-1 + 2i - .5 + 1E2; z = z");
+        let vars = formula.initial_variables(SimdComplex::<2>::default(), seed());
+        assert_eq!(vars[0], SimdComplex::splat(0.15 - 0.5 + 100.0, 2.0));
+    }
+
+    fn check_error(source: &str, position: usize, message: &str) {
+        let error: FormulaError = Formula::parse(source).unwrap_err();
+        assert_eq!(error.position, position, "{}", error.pretty(source));
+        assert!(
+            error.message.starts_with(message),
+            "'{}' does not start with '{}'",
+            error.message,
+            message
+        );
+    }
+
+    fn parse(source: &str) -> Formula {
+        Formula::parse(source).unwrap_or_else(|e| panic!("{}", e.pretty(source)))
+    }
+
+    fn seed<const N: usize>() -> SimdComplex<N> {
+        SimdComplex::splat(-0.75, 0.2)
+    }
+
+    fn sample_points() -> impl Iterator<Item = SimdComplex<8>> {
+        (-20..20)
+            .cartesian_product(-20..20)
+            .map(|(x, y)| (x as f64 * 0.1 + 0.013, y as f64 * 0.1 + 0.007))
+            .chunks(8)
+            .into_iter()
+            .map(|chunk| {
+                let points = chunk.collect_vec();
+                SimdComplex {
+                    re: std::array::from_fn(|i| points[i].0).into(),
+                    im: std::array::from_fn(|i| points[i].1).into(),
+                }
+            })
+            .collect_vec()
+            .into_iter()
+    }
+}
@@ -0,0 +1,504 @@
+//! Golden-image regression tests.
+//!
+//! Renders small fixed views of every rule with every palette and compares them
+//! with the reference images in `tests/golden`. Images are stored as binary PPM files.
+//! On failure, the actual image and a diff image are written to `target/golden-diff`.
+//! Run the tests with `UPDATE_GOLDEN=1` to regenerate the reference images.
+
+use crate::{
+    buddhabrot::BuddhabrotRenderer,
+    color::{
+        palettes, BasinColoring, ColorSpace, Colormap, Cosine, DistanceColoring, Lighting, Palette,
+        Rgb, Transfer, TrapColoring,
+    },
+    image::Image,
+    polynomial::{Polynomial, RootFindingMethod},
+    render::Renderer,
+    rules::{self, Bailout, BailoutTest, OrbitAverage, OrbitTrap, RootFindingParams, TrapShape},
+    simd::{Array, SimdComplex},
+    utils::{Complex, FnSync},
+    view::ComplexPlaneView,
+};
+use itertools::Itertools;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const WIDTH: usize = 48;
+const HEIGHT: usize = 32;
+/// Maximum difference of a single color channel for two pixels to be considered equal.
+const CHANNEL_TOLERANCE: u8 = 2;
+/// Maximum fraction of pixels that can differ before the image is considered changed.
+const MAX_MISMATCH_RATIO: f64 = 0.005;
+const MISMATCH_COLOR: Rgb = Rgb(255, 0, 0);
+
+#[test]
+fn julia_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(0.0, 0.0), 2.5);
+    let seed = SimdComplex::splat(-0.75, 0.2);
+    check_all_palettes("julia", &view, |z| rules::julia(z, seed, Bailout::DEFAULT));
+}
+
+#[test]
+fn mandelbrot_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    check_all_palettes("mandelbrot", &view, |c| {
+        rules::mandelbrot(c, Bailout::DEFAULT)
+    });
+}
+
+#[test]
+fn color_spaces_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let stops = [
+        (0.0, Rgb(0, 0, 80)),
+        (0.1, Rgb(255, 200, 0)),
+        (0.3, Rgb(200, 0, 120)),
+        (1.0, Rgb(0, 0, 0)),
+    ];
+    assert_all_ok(ColorSpace::ALL.into_iter().map(|space| {
+        let palette = Palette::from_stops(&stops, space);
+        check_palette(
+            "mandelbrot",
+            &view,
+            (
+                &format!("stops_{}", space.name().replace('-', "_")),
+                &palette,
+            ),
+            |c| rules::mandelbrot(c, Bailout::DEFAULT),
+        )
+    }));
+}
+
+#[test]
+fn generated_palettes_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let colormaps =
+        Colormap::ALL.map(|colormap| (colormap.name(), Palette::from_colormap(colormap)));
+    assert_all_ok(
+        [("cosine", Palette::from_cosine(Cosine::DEFAULT))]
+            .iter()
+            .chain(&colormaps)
+            .map(|(name, palette)| {
+                check_palette("mandelbrot", &view, (name, palette), |c| {
+                    rules::mandelbrot(c, Bailout::DEFAULT)
+                })
+            }),
+    );
+}
+
+#[test]
+fn nova_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.3, 0.0), 1.5);
+    check_all_palettes("nova", &view, |c| {
+        rules::nova(
+            SimdComplex::splat(1.0, 0.0),
+            c,
+            RootFindingParams::DEFAULT,
+            |z| z * z * z - 1.0,
+            |z| z * z * 3.0,
+        )
+    });
+}
+
+#[test]
+fn newton_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(0.0, 0.0), 3.0);
+    check_all_palettes("newton", &view, |z| {
+        rules::newton(z, |z| z * z * z - 1.0, |z| z * z * 3.0)
+    });
+}
+
+#[test]
+fn abs_and_conjugate_variants_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.4, 0.0), 3.5);
+    let palette = ("blue_green", &*palettes::BLUE_GREEN);
+    assert_all_ok([
+        check_palette("burning_ship", &view, palette, |c| {
+            rules::burning_ship(c, Bailout::DEFAULT)
+        }),
+        check_palette("tricorn", &view, palette, |c| {
+            rules::tricorn(c, Bailout::DEFAULT)
+        }),
+        check_palette("celtic", &view, palette, |c| {
+            rules::celtic(c, Bailout::DEFAULT)
+        }),
+        check_palette("perpendicular", &view, palette, |c| {
+            rules::perpendicular_mandelbrot(c, Bailout::DEFAULT)
+        }),
+        check_palette("buffalo", &view, palette, |c| {
+            rules::buffalo(c, Bailout::DEFAULT)
+        }),
+        check_palette("heart", &view, palette, |c| {
+            rules::heart(c, Bailout::DEFAULT)
+        }),
+    ]);
+}
+
+#[test]
+fn bailout_tests_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let palette = ("blue_green", &*palettes::BLUE_GREEN);
+    // The default norm test is already covered by the Mandelbrot images
+    assert_all_ok(
+        BailoutTest::ALL
+            .into_iter()
+            .filter(|&test| test != BailoutTest::Norm)
+            .map(|test| {
+                let bailout = Bailout { radius: 2.0, test };
+                let name = format!("mandelbrot_bailout_{}", test.name());
+                check_palette(&name, &view, palette, |c| rules::mandelbrot(c, bailout))
+            }),
+    );
+}
+
+#[test]
+fn phoenix_magnet_and_lambda_match_golden_images() {
+    let palette = ("blue_green", &*palettes::BLUE_GREEN);
+    let view =
+        |offset, scale| ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, offset, scale);
+    let p = SimdComplex::splat(-0.5, 0.0);
+    assert_all_ok([
+        check_palette(
+            "phoenix",
+            &view(Complex::new(0.0, 0.0), 2.5),
+            palette,
+            |c| rules::phoenix(c, p, Bailout::DEFAULT),
+        ),
+        check_palette(
+            "magnet1",
+            &view(Complex::new(1.5, 0.0), 4.0),
+            palette,
+            rules::magnet1,
+        ),
+        check_palette(
+            "magnet2",
+            &view(Complex::new(1.0, 0.0), 4.0),
+            palette,
+            rules::magnet2,
+        ),
+        check_palette("lambda", &view(Complex::new(1.0, 0.0), 3.5), palette, |c| {
+            rules::lambda(c, Bailout::DEFAULT)
+        }),
+    ]);
+}
+
+#[test]
+fn distance_coloring_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let pixel_size = view.pixel_size();
+    let coloring = DistanceColoring::DEFAULT;
+    assert_all_ok([
+        check_image("mandelbrot_distance_boundary", &view, |c| {
+            coloring.boundary_array(c, pixel_size, |c| rules::mandelbrot_with_distance(c).1)
+        }),
+        check_image("mandelbrot_distance_blue_green", &view, |c| {
+            let distances = rules::mandelbrot_with_distance(c).1;
+            coloring.palette_color_array(&palettes::BLUE_GREEN, distances, pixel_size)
+        }),
+    ]);
+}
+
+#[test]
+fn relief_shading_matches_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let lighting = Lighting::DEFAULT;
+    assert_all_ok(
+        [
+            ("blue_green", &*palettes::BLUE_GREEN),
+            ("yellow_red", &*palettes::YELLOW_RED),
+        ]
+        .into_iter()
+        .map(|(palette_name, palette)| {
+            check_image(&format!("mandelbrot_relief_{}", palette_name), &view, |c| {
+                let (counts, z, dz) = rules::mandelbrot_with_derivative(c);
+                lighting.shade_array(palette.color_array(counts), counts, z, dz)
+            })
+        }),
+    );
+}
+
+#[test]
+fn orbit_traps_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    // A red to blue gradient from left to right, and dark to bright from bottom to top
+    let image = Image {
+        width: 8,
+        height: 8,
+        pixels: (0..64)
+            .map(|i| Rgb((7 - i % 8) * 32, (7 - i / 8) * 32, (i % 8) * 32))
+            .collect(),
+    };
+    let coloring = TrapColoring { image: Some(image) };
+    assert_all_ok(TrapShape::ALL.into_iter().map(|shape| {
+        let trap = OrbitTrap {
+            shape,
+            center: Complex::new(-0.5, 0.25),
+            size: 0.5,
+        };
+        check_image(&format!("mandelbrot_trap_{}", shape.name()), &view, |c| {
+            let (distances, closest) = rules::mandelbrot_with_trap(c, Bailout::DEFAULT, trap);
+            coloring.color_array(&palettes::BLUE_GREEN, trap, distances, closest)
+        })
+    }));
+}
+
+#[test]
+fn orbit_averages_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.6, 0.0), 2.6);
+    let bailout = Bailout {
+        radius: 1000.0,
+        ..Bailout::DEFAULT
+    };
+    assert_all_ok(
+        [
+            ("stripe", OrbitAverage::Stripe { density: 5.0 }),
+            ("triangle", OrbitAverage::TriangleInequality),
+            ("curvature", OrbitAverage::Curvature),
+        ]
+        .into_iter()
+        .flat_map(|(name, average)| {
+            [
+                check_image(&format!("mandelbrot_{}_average", name), &view, |c| {
+                    palettes::BLUE_GREEN
+                        .color_array(rules::mandelbrot_with_average(c, bailout, average))
+                }),
+                check_image(&format!("nova_{}_average", name), &view, |z| {
+                    let c = SimdComplex::splat(0.1, 0.1);
+                    let f = |z| z * z * z - 1.0;
+                    let df = |z| z * z * 3.0;
+                    let params = RootFindingParams::DEFAULT;
+                    palettes::BLUE_GREEN
+                        .color_array(rules::nova_with_average(z, c, params, f, df, average))
+                }),
+            ]
+        }),
+    );
+}
+
+#[test]
+fn transfer_functions_match_golden_images() {
+    // Zoomed in on the boundary, where escape values only cover a narrow range
+    let view = ComplexPlaneView::with_offset_and_scale(
+        WIDTH,
+        HEIGHT,
+        Complex::new(-0.7435, 0.1314),
+        0.002,
+    );
+    let values = Renderer::new()
+        .render_with_lanes(&view, |c: SimdComplex| {
+            rules::mandelbrot(c, Bailout::DEFAULT)
+        })
+        .collect_vec();
+    assert_all_ok(Transfer::ALL.into_iter().map(|transfer| {
+        let table = transfer.table(&values);
+        let image = Image {
+            width: WIDTH,
+            height: HEIGHT,
+            pixels: values
+                .iter()
+                .map(|&v| palettes::RAINBOW_DARK.color(table[v as usize]))
+                .collect(),
+        };
+        check_golden(&format!("mandelbrot_transfer_{}", transfer.name()), &image)
+    }));
+}
+
+#[test]
+fn buddhabrot_variants_match_golden_images() {
+    const SAMPLES: usize = 50_000;
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(-0.5, 0.0), 3.0);
+    assert_all_ok(
+        [
+            ("buddhabrot", BuddhabrotRenderer::buddhabrot(100)),
+            ("nebulabrot", BuddhabrotRenderer::nebulabrot([200, 50, 10])),
+            ("anti_buddhabrot", BuddhabrotRenderer::anti_buddhabrot(50)),
+        ]
+        .into_iter()
+        .map(|(name, renderer)| {
+            let image = Image {
+                width: WIDTH,
+                height: HEIGHT,
+                pixels: renderer.with_samples(SAMPLES).render(&view),
+            };
+            check_golden(name, &image)
+        }),
+    );
+}
+
+#[test]
+fn newton_basins_match_golden_image() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(0.0, 0.0), 3.0);
+    let polynomial = Polynomial::from_real(&[1.0, 0.0, -2.0, 2.0]);
+    let derivative = polynomial.derivative();
+    let basins = BasinColoring::from_polynomial(&polynomial).unwrap();
+    let result = check_image("newton_basins", &view, |z| {
+        let (counts, roots) = rules::newton_with_root(
+            z,
+            RootFindingParams::DEFAULT,
+            |z| polynomial.eval(z),
+            |z| derivative.eval(z),
+        );
+        basins.color_array(counts, roots, RootFindingParams::DEFAULT.tolerance)
+    });
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+#[test]
+fn root_finding_basins_match_golden_images() {
+    let view = ComplexPlaneView::with_offset_and_scale(WIDTH, HEIGHT, Complex::new(0.0, 0.0), 3.0);
+    let polynomial = Polynomial::from_real(&[1.0, 0.0, 0.0, -1.0]);
+    let basins = BasinColoring::from_polynomial(&polynomial).unwrap();
+    assert_all_ok(RootFindingMethod::ALL.into_iter().map(|method| {
+        check_image(&format!("basins_{}", method.name()), &view, |z| {
+            let (counts, roots) = polynomial.find_root(
+                method,
+                RootFindingParams::DEFAULT,
+                SimdComplex::default(),
+                z,
+            );
+            basins.color_array(counts, roots, RootFindingParams::DEFAULT.tolerance)
+        })
+    }));
+}
+
+fn all_palettes() -> [(&'static str, &'static Palette); 5] {
+    [
+        ("cyan", &palettes::CYAN),
+        ("blue_green", &palettes::BLUE_GREEN),
+        ("yellow_red", &palettes::YELLOW_RED),
+        ("rainbow_dark", &palettes::RAINBOW_DARK),
+        ("rainbow_light", &palettes::RAINBOW_LIGHT),
+    ]
+}
+
+fn check_all_palettes(
+    rule_name: &str,
+    view: &ComplexPlaneView,
+    rule: impl FnSync(SimdComplex) -> Array<u8>,
+) {
+    assert_all_ok(
+        all_palettes()
+            .into_iter()
+            .map(|palette| check_palette(rule_name, view, palette, &rule)),
+    );
+}
+
+/// Fails with all error messages if any of the checks failed, so that one run
+/// reports every mismatching image.
+fn assert_all_ok(results: impl IntoIterator<Item = Result<(), String>>) {
+    let failures = results.into_iter().filter_map(Result::err).collect_vec();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+fn check_palette(
+    rule_name: &str,
+    view: &ComplexPlaneView,
+    (palette_name, palette): (&str, &Palette),
+    rule: impl FnSync(SimdComplex) -> Array<u8>,
+) -> Result<(), String> {
+    check_image(&format!("{}_{}", rule_name, palette_name), view, |z| {
+        palette.color_array(rule(z))
+    })
+}
+
+fn check_image(
+    name: &str,
+    view: &ComplexPlaneView,
+    color_computer: impl FnSync(SimdComplex) -> Array<Rgb>,
+) -> Result<(), String> {
+    let pixels = Renderer::new()
+        .render_with_lanes(view, |z: SimdComplex| color_computer(z))
+        .collect_vec();
+    let image = Image {
+        width: view.width(),
+        height: view.height(),
+        pixels,
+    };
+    check_golden(name, &image)
+}
+
+fn check_golden(name: &str, actual: &Image) -> Result<(), String> {
+    let reference_path = manifest_path("tests/golden").join(format!("{}.ppm", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return actual
+            .write_ppm(&reference_path)
+            .map_err(|e| format!("{}: cannot write reference image: {}", name, e));
+    }
+
+    let expected = Image::read_ppm(&reference_path).map_err(|e| {
+        format!(
+            "{}: cannot read reference image {} ({}), run with UPDATE_GOLDEN=1 to create it",
+            name,
+            reference_path.display(),
+            e
+        )
+    })?;
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(format!(
+            "{}: expected a {}x{} image, got {}x{}",
+            name, expected.width, expected.height, actual.width, actual.height
+        ));
+    }
+
+    let mismatches = expected
+        .pixels
+        .iter()
+        .zip(&actual.pixels)
+        .filter(|(&e, &a)| !pixels_match(e, a))
+        .count();
+    let mismatch_ratio = mismatches as f64 / expected.pixels.len() as f64;
+    if mismatch_ratio <= MAX_MISMATCH_RATIO {
+        return Ok(());
+    }
+
+    let diff_dir = manifest_path("target/golden-diff");
+    let written = fs::create_dir_all(&diff_dir)
+        .and_then(|_| actual.write_ppm(&diff_dir.join(format!("{}.actual.ppm", name))))
+        .and_then(|_| {
+            diff_image(&expected, actual).write_ppm(&diff_dir.join(format!("{}.diff.ppm", name)))
+        });
+    Err(format!(
+        "{}: {} of {} pixels differ ({:.2}%), diff images {}",
+        name,
+        mismatches,
+        expected.pixels.len(),
+        mismatch_ratio * 100.0,
+        match written {
+            Ok(_) => format!("written to {}", diff_dir.display()),
+            Err(e) => format!("could not be written: {}", e),
+        }
+    ))
+}
+
+fn pixels_match(lhs: Rgb, rhs: Rgb) -> bool {
+    [(lhs.0, rhs.0), (lhs.1, rhs.1), (lhs.2, rhs.2)]
+        .into_iter()
+        .all(|(l, r)| l.abs_diff(r) <= CHANNEL_TOLERANCE)
+}
+
+fn manifest_path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+/// Highlights mismatched pixels on top of a darkened copy of the expected image.
+fn diff_image(expected: &Image, actual: &Image) -> Image {
+    let pixels = expected
+        .pixels
+        .iter()
+        .zip(&actual.pixels)
+        .map(|(&e, &a)| {
+            if pixels_match(e, a) {
+                Rgb(e.0 / 4, e.1 / 4, e.2 / 4)
+            } else {
+                MISMATCH_COLOR
+            }
+        })
+        .collect();
+    Image {
+        width: expected.width,
+        height: expected.height,
+        pixels,
+    }
+}
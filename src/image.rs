@@ -0,0 +1,98 @@
+//! Minimal support for binary PPM images, used to save renders and golden images.
+
+use crate::color::Rgb;
+use std::{fs, io, path::Path};
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn read_ppm(path: &Path) -> io::Result<Image> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let bytes = fs::read(path)?;
+
+        // The header consists of 4 whitespace-separated tokens: magic, width, height, max value
+        let mut header_end = 0;
+        let mut tokens = Vec::with_capacity(4);
+        while tokens.len() < 4 {
+            let start = header_end
+                + bytes[header_end..]
+                    .iter()
+                    .position(|b| !b.is_ascii_whitespace())
+                    .ok_or_else(|| invalid("truncated header"))?;
+            let len = bytes[start..]
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .ok_or_else(|| invalid("truncated header"))?;
+            tokens.push(String::from_utf8_lossy(&bytes[start..start + len]).into_owned());
+            // Skip the single whitespace character that ends the token
+            header_end = start + len + 1;
+        }
+
+        if tokens[0] != "P6" || tokens[3] != "255" {
+            return Err(invalid("expected a binary PPM with 8-bit channels"));
+        }
+        let parse_dimension = |token: &str| token.parse().map_err(|_| invalid("invalid dimension"));
+        let width = parse_dimension(&tokens[1])?;
+        let height = parse_dimension(&tokens[2])?;
+        if width == 0 || height == 0 {
+            return Err(invalid("empty image"));
+        }
+
+        let data = &bytes[header_end..];
+        if data.len() != width * height * 3 {
+            return Err(invalid("pixel data does not match image dimensions"));
+        }
+        let pixels = data
+            .chunks_exact(3)
+            .map(|p| Rgb(p[0], p[1], p[2]))
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.pixels.iter().flat_map(|p| [p.0, p.1, p.2]));
+        fs::write(path, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Rgb, image::Image};
+    use std::{fs, io, path::PathBuf};
+
+    #[test]
+    fn images_round_trip_and_empty_images_are_rejected() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/image-test");
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("pixels.ppm");
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Rgb(255, 0, 0), Rgb(0, 0, 255)],
+        };
+        image.write_ppm(&path).unwrap();
+        let read = Image::read_ppm(&path).unwrap();
+        assert_eq!((read.width, read.height), (2, 1));
+        assert_eq!(read.pixels, image.pixels);
+
+        for (name, header) in [
+            ("empty.ppm", "P6\n0 0\n255\n"),
+            ("no-rows.ppm", "P6\n4 0\n255\n"),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, header).unwrap();
+            let error = Image::read_ppm(&path).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", name);
+        }
+    }
+}
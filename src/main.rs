@@ -1,9 +1,31 @@
-#![feature(unboxed_closures, tuple_trait, const_option, portable_simd)]
+#![feature(unboxed_closures, tuple_trait, portable_simd)]
 
-use crate::{app::FractalExplorerApp, color::palettes, simd::SimdComplex};
+use crate::{
+    app::{FractalExplorerApp, PaletteOrRgb, RuleKernel, Shaded},
+    buddhabrot::BuddhabrotRenderer,
+    color::{palettes, BasinColoring, DistanceColoring, Lighting, Palette, Rgb, TrapColoring},
+    formula::Formula,
+    image::Image,
+    polynomial::{Polynomial, RootFindingMethod},
+    rules::{OrbitAverage, RuleParams},
+    simd::{Array, SimdComplex, SimdDouble},
+    utils::Complex,
+    view::ComplexPlaneView,
+};
+use std::{path::Path, process};
 
 mod app;
+mod autodiff;
+mod bench;
+mod buddhabrot;
 mod color;
+mod cpu;
+mod formula;
+#[cfg(test)]
+mod golden_tests;
+mod image;
+mod palette_file;
+mod polynomial;
 mod render;
 mod rules;
 mod simd;
@@ -12,15 +34,582 @@ mod view;
 
 const WIDTH: usize = 1280;
 const HEIGHT: usize = 720;
+const DEFAULT_NEWTON_COEFFICIENTS: &str = "1, 0, 0, -1";
+const BUDDHABROT_MAX_ITERS: u32 = 1000;
+/// The classic Nebulabrot limits for the red, green and blue channels.
+const NEBULABROT_ITERATION_LIMITS: [u32; 3] = [5000, 500, 50];
+const ANTI_BUDDHABROT_MAX_ITERS: u32 = 100;
+const DEFAULT_STRIPE_DENSITY: f64 = 5.0;
+/// Orbit averages are only smooth with a large escape radius, so smaller radii are raised to this.
+const MIN_AVERAGE_BAILOUT_RADIUS: f64 = 100.0;
+
+/// Escape-time rules selectable with `--rule`.
+/// Mandelbrot-style rules use the pixel as parameter (and the seed as an extra one if needed),
+/// while Julia-style rules start from the pixel and use the seed as parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeTimeRule {
+    Phoenix,
+    PhoenixJulia,
+    Magnet1,
+    Magnet1Julia,
+    Magnet2,
+    Magnet2Julia,
+    Lambda,
+    LambdaJulia,
+}
+
+impl EscapeTimeRule {
+    const ALL: [Self; 8] = [
+        Self::Phoenix,
+        Self::PhoenixJulia,
+        Self::Magnet1,
+        Self::Magnet1Julia,
+        Self::Magnet2,
+        Self::Magnet2Julia,
+        Self::Lambda,
+        Self::LambdaJulia,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Phoenix => "phoenix",
+            Self::PhoenixJulia => "phoenix-julia",
+            Self::Magnet1 => "magnet1",
+            Self::Magnet1Julia => "magnet1-julia",
+            Self::Magnet2 => "magnet2",
+            Self::Magnet2Julia => "magnet2-julia",
+            Self::Lambda => "lambda",
+            Self::LambdaJulia => "lambda-julia",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+impl RuleKernel for EscapeTimeRule {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        let seed = SimdComplex::from_complex(params.seed);
+        match self {
+            Self::Phoenix => rules::phoenix(z, seed, params.bailout),
+            Self::PhoenixJulia => {
+                // Classic Phoenix Julia sets have real parameters, both packed into the seed
+                let c = SimdComplex::splat(params.seed.re, 0.0);
+                let p = SimdComplex::splat(params.seed.im, 0.0);
+                rules::phoenix_julia(z, c, p, params.bailout)
+            }
+            Self::Magnet1 => rules::magnet1(z),
+            Self::Magnet1Julia => rules::magnet1_julia(z, seed),
+            Self::Magnet2 => rules::magnet2(z),
+            Self::Magnet2Julia => rules::magnet2_julia(z, seed),
+            Self::Lambda => rules::lambda(z, params.bailout),
+            Self::LambdaJulia => rules::lambda_julia(z, seed, params.bailout),
+        }
+    }
+}
+
+/// Whether the rule of a mode starts from the pixel and uses the seed as parameter (Julia),
+/// or uses the pixel as parameter (Mandelbrot).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Julia,
+    Mandelbrot,
+}
+
+impl Family {
+    /// Parses an optional family argument, which defaults to Julia.
+    fn from_arg(arg: Option<&String>) -> Option<Self> {
+        match arg.map(String::as_str) {
+            None | Some("julia") => Some(Self::Julia),
+            Some("mandelbrot") => Some(Self::Mandelbrot),
+            Some(_) => None,
+        }
+    }
+
+    /// Estimates the distance from each point to the boundary of the set.
+    fn distance<const N: usize>(self, z: SimdComplex<N>, params: RuleParams) -> SimdDouble<N> {
+        match self {
+            Self::Julia => rules::julia_with_distance(z, SimdComplex::from_complex(params.seed)).1,
+            Self::Mandelbrot => rules::mandelbrot_with_distance(z).1,
+        }
+    }
+}
+
+/// The default mode: Multibrot Julia sets, with the exponent controlled in the app.
+struct MultibrotJuliaKernel;
+
+impl RuleKernel for MultibrotJuliaKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        let seed = SimdComplex::from_complex(params.seed);
+        rules::multibrot_julia(z, seed, params.exponent, params.bailout)
+    }
+}
+
+impl RuleKernel for Formula {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        rules::formula(self, z, SimdComplex::from_complex(params.seed))
+    }
+}
+
+struct NewtonKernel {
+    polynomial: Polynomial,
+    method: RootFindingMethod,
+    basins: BasinColoring,
+}
+
+impl RuleKernel for NewtonKernel {
+    type Output = Rgb;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<Rgb, N> {
+        let c = SimdComplex::default();
+        let (counts, roots) = self
+            .polynomial
+            .find_root(self.method, params.root_finding, c, z);
+        self.basins
+            .color_array(counts, roots, params.root_finding.tolerance)
+    }
+}
+
+/// Nova fractals of a root-finding method, starting from the pixel and using the seed as `c`.
+struct NovaKernel {
+    polynomial: Polynomial,
+    method: RootFindingMethod,
+}
+
+impl RuleKernel for NovaKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        let c = SimdComplex::from_complex(params.seed);
+        self.polynomial
+            .find_root(self.method, params.root_finding, c, z)
+            .0
+    }
+}
+
+/// Colors distances to the boundary with the palette of the app.
+struct DistanceKernel {
+    family: Family,
+    coloring: DistanceColoring,
+}
+
+impl RuleKernel for DistanceKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        let distances = self.family.distance(z, params);
+        self.coloring
+            .palette_index_array(distances, params.pixel_size)
+    }
+}
+
+/// Draws only the boundary, in black on white.
+struct BoundaryKernel {
+    family: Family,
+    coloring: DistanceColoring,
+}
+
+impl RuleKernel for BoundaryKernel {
+    type Output = Rgb;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<Rgb, N> {
+        self.coloring
+            .boundary_array(z, params.pixel_size, |z| self.family.distance(z, params))
+    }
+}
+
+struct ReliefKernel {
+    family: Family,
+    lighting: Lighting,
+}
+
+impl RuleKernel for ReliefKernel {
+    type Output = Shaded;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<Shaded, N> {
+        let (counts, z, dz) = match self.family {
+            Family::Julia => {
+                rules::julia_with_derivative(z, SimdComplex::from_complex(params.seed))
+            }
+            Family::Mandelbrot => rules::mandelbrot_with_derivative(z),
+        };
+        let shades = self.lighting.shade_of_array(counts, z, dz);
+        std::array::from_fn(|i| Shaded {
+            value: counts[i],
+            shade: shades[i],
+        })
+    }
+}
+
+struct TrapKernel {
+    family: Family,
+    coloring: TrapColoring,
+}
+
+impl RuleKernel for TrapKernel {
+    type Output = PaletteOrRgb;
+
+    const USES_TRAP: bool = true;
+
+    fn compute<const N: usize>(
+        &self,
+        z: SimdComplex<N>,
+        params: RuleParams,
+    ) -> Array<PaletteOrRgb, N> {
+        let (distances, closest) = match self.family {
+            Family::Julia => {
+                let seed = SimdComplex::from_complex(params.seed);
+                rules::julia_with_trap(z, seed, params.bailout, params.trap)
+            }
+            Family::Mandelbrot => rules::mandelbrot_with_trap(z, params.bailout, params.trap),
+        };
+        std::array::from_fn(|i| {
+            let closest = Complex::new(closest.re[i], closest.im[i]);
+            match self
+                .coloring
+                .image_color(params.trap, distances[i], closest)
+            {
+                Some(color) => PaletteOrRgb::Rgb(color),
+                None => {
+                    PaletteOrRgb::Palette(self.coloring.palette_index(params.trap, distances[i]))
+                }
+            }
+        })
+    }
+}
+
+struct AverageKernel {
+    family: Family,
+    average: OrbitAverage,
+}
+
+impl RuleKernel for AverageKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, mut params: RuleParams) -> Array<u8, N> {
+        params.bailout.radius = params.bailout.radius.max(MIN_AVERAGE_BAILOUT_RADIUS);
+        match self.family {
+            Family::Julia => {
+                let seed = SimdComplex::from_complex(params.seed);
+                rules::julia_with_average(z, seed, params.bailout, self.average)
+            }
+            Family::Mandelbrot => rules::mandelbrot_with_average(z, params.bailout, self.average),
+        }
+    }
+}
+
+/// Nova fractals of the default Newton polynomial, starting from the pixel and using the seed as parameter.
+struct NovaAverageKernel {
+    polynomial: Polynomial,
+    derivative: Polynomial,
+    average: OrbitAverage,
+}
+
+impl RuleKernel for NovaAverageKernel {
+    type Output = u8;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>, params: RuleParams) -> Array<u8, N> {
+        rules::nova_with_average(
+            z,
+            SimdComplex::from_complex(params.seed),
+            params.root_finding,
+            |z| self.polynomial.eval(z),
+            |z| self.derivative.eval(z),
+            self.average,
+        )
+    }
+}
+
+const TITLE: &str = "Fractal Explorer. (WASD to move, Arrow Keys to modify seed, R to reset)";
 
 fn main() {
-    let mut app = FractalExplorerApp::new(
-        "Fractal Explorer. (WASD to move, Arrow Keys to modify seed, R to reset)",
-        WIDTH,
-        HEIGHT,
-        |z, seed| {
-            palettes::BLUE_GREEN.color_array(rules::julia(z, SimdComplex::from_complex(seed)))
-        },
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let loaded_palettes = take_palette_dir(&mut args);
+    match args.first().map(String::as_str) {
+        Some("--bench") => {
+            let output = args.get(1).map(Path::new);
+            bench::run(output).unwrap_or_else(|e| panic!("Cannot write benchmark report: {}", e));
+        }
+        Some("--export-palette") => {
+            let (Some(name), Some(output)) = (args.get(1), args.get(2)) else {
+                exit_with_export_palette_usage();
+            };
+            let palette = palettes::all()
+                .into_iter()
+                .find(|(builtin, _)| builtin == name)
+                .map(|(_, palette)| palette)
+                .or_else(|| {
+                    loaded_palettes
+                        .iter()
+                        .find(|(loaded, _)| loaded == name)
+                        .map(|(_, palette)| palette)
+                })
+                .unwrap_or_else(|| {
+                    eprintln!("Unknown palette: '{}'", name);
+                    process::exit(2);
+                });
+            palette_file::write(palette, Path::new(output)).unwrap_or_else(|e| {
+                eprintln!("Cannot write palette '{}': {}", output, e);
+                process::exit(1);
+            });
+        }
+        Some("--formula") => {
+            let Some(source) = args.get(1) else {
+                eprintln!("Usage: fractals --formula \"z = z^2 + c\"");
+                process::exit(2);
+            };
+            let formula = Formula::parse(source).unwrap_or_else(|e| {
+                eprintln!("{}", e.pretty(source));
+                process::exit(1);
+            });
+            run_app(loaded_palettes, formula);
+        }
+        Some(mode @ ("--newton" | "--nova")) => {
+            let coefficients = args
+                .get(1)
+                .map_or(DEFAULT_NEWTON_COEFFICIENTS, String::as_str);
+            let method = args.get(2).map_or(Some(RootFindingMethod::Newton), |name| {
+                RootFindingMethod::from_name(name)
+            });
+            let (polynomial, method) = match (parse_polynomial(coefficients), method) {
+                (Ok(polynomial), Some(method)) => (polynomial, method),
+                (result, _) => {
+                    eprintln!(
+                        "{}",
+                        result.err().unwrap_or_else(|| "Unknown method".to_string())
+                    );
+                    eprintln!(
+                        "Usage: fractals {} \"1, 0, 0, -1\" [{}] (coefficients, highest degree first)",
+                        mode,
+                        RootFindingMethod::ALL.map(RootFindingMethod::name).join("|")
+                    );
+                    process::exit(2);
+                }
+            };
+            if mode == "--nova" {
+                run_app(loaded_palettes, NovaKernel { polynomial, method });
+            } else {
+                let basins = BasinColoring::from_polynomial(&polynomial).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                });
+                run_app(
+                    loaded_palettes,
+                    NewtonKernel {
+                        polynomial,
+                        method,
+                        basins,
+                    },
+                );
+            }
+        }
+        Some("--buddhabrot") => {
+            let renderer = match args.get(1).map(String::as_str) {
+                Some("buddhabrot") => BuddhabrotRenderer::buddhabrot(BUDDHABROT_MAX_ITERS),
+                Some("nebulabrot") => BuddhabrotRenderer::nebulabrot(NEBULABROT_ITERATION_LIMITS),
+                Some("anti") => BuddhabrotRenderer::anti_buddhabrot(ANTI_BUDDHABROT_MAX_ITERS),
+                _ => exit_with_buddhabrot_usage(),
+            };
+            let Some(output) = args.get(2).map(Path::new) else {
+                exit_with_buddhabrot_usage();
+            };
+            let renderer = match args.get(3).map(|samples| samples.parse()) {
+                Some(Ok(samples)) => renderer.with_samples(samples),
+                Some(Err(_)) => {
+                    eprintln!("Invalid sample count: '{}'", args[3]);
+                    process::exit(2);
+                }
+                None => renderer,
+            };
+            let view = ComplexPlaneView::with_offset_and_scale(
+                WIDTH,
+                HEIGHT,
+                Complex::new(-0.5, 0.0),
+                3.0,
+            );
+            let image = Image {
+                width: WIDTH,
+                height: HEIGHT,
+                pixels: renderer.render(&view),
+            };
+            image
+                .write_ppm(output)
+                .unwrap_or_else(|e| panic!("Cannot write image: {}", e));
+        }
+        Some("--distance") => {
+            let Some(family) = Family::from_arg(args.get(2)) else {
+                exit_with_distance_usage();
+            };
+            let coloring = DistanceColoring::DEFAULT;
+            match args.get(1).map(String::as_str) {
+                Some("boundary") => run_app(loaded_palettes, BoundaryKernel { family, coloring }),
+                Some("palette") => run_app(loaded_palettes, DistanceKernel { family, coloring }),
+                _ => exit_with_distance_usage(),
+            }
+        }
+        Some("--relief") => {
+            let Some(family) = Family::from_arg(args.get(1)) else {
+                exit_with_relief_usage();
+            };
+            let lighting = match args.get(2).map(|lighting| parse_lighting(lighting)) {
+                Some(Ok(lighting)) => lighting,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    exit_with_relief_usage();
+                }
+                None => Lighting::DEFAULT,
+            };
+            run_app(loaded_palettes, ReliefKernel { family, lighting });
+        }
+        Some("--trap") => {
+            let Some(family) = Family::from_arg(args.get(1)) else {
+                exit_with_trap_usage();
+            };
+            let image = args.get(2).map(|path| {
+                Image::read_ppm(Path::new(path)).unwrap_or_else(|e| {
+                    eprintln!("Cannot read trap image '{}': {}", path, e);
+                    process::exit(1);
+                })
+            });
+            let coloring = TrapColoring { image };
+            run_app(loaded_palettes, TrapKernel { family, coloring });
+        }
+        Some("--average") => {
+            let average = match args.get(1).map(String::as_str) {
+                Some("stripe") => OrbitAverage::Stripe {
+                    density: DEFAULT_STRIPE_DENSITY,
+                },
+                Some("triangle") => OrbitAverage::TriangleInequality,
+                Some("curvature") => OrbitAverage::Curvature,
+                _ => exit_with_average_usage(),
+            };
+            if args.get(2).is_some_and(|arg| arg == "nova") {
+                let polynomial = parse_polynomial(DEFAULT_NEWTON_COEFFICIENTS)
+                    .expect("the default coefficients are valid");
+                let derivative = polynomial.derivative();
+                run_app(
+                    loaded_palettes,
+                    NovaAverageKernel {
+                        polynomial,
+                        derivative,
+                        average,
+                    },
+                );
+            } else {
+                let Some(family) = Family::from_arg(args.get(2)) else {
+                    exit_with_average_usage();
+                };
+                run_app(loaded_palettes, AverageKernel { family, average });
+            }
+        }
+        Some("--rule") => {
+            let Some(rule) = args.get(1).and_then(|name| EscapeTimeRule::from_name(name)) else {
+                eprintln!(
+                    "Usage: fractals --rule <{}>",
+                    EscapeTimeRule::ALL.map(EscapeTimeRule::name).join("|")
+                );
+                process::exit(2);
+            };
+            run_app(loaded_palettes, rule);
+        }
+        _ => run_app(loaded_palettes, MultibrotJuliaKernel),
+    }
+}
+
+/// Removes the `--palettes <dir>` option from the arguments and reads the palettes in the directory.
+fn take_palette_dir(args: &mut Vec<String>) -> Vec<(String, Palette)> {
+    let Some(index) = args.iter().position(|arg| arg == "--palettes") else {
+        return Vec::new();
+    };
+    let Some(dir) = args.get(index + 1).cloned() else {
+        eprintln!("Usage: fractals --palettes <dir> [mode...]");
+        process::exit(2);
+    };
+    args.drain(index..index + 2);
+    palette_file::read_dir(Path::new(&dir)).unwrap_or_else(|e| {
+        eprintln!("Cannot read palettes from '{}': {}", dir, e);
+        process::exit(1);
+    })
+}
+
+/// Parses comma-separated real coefficients, highest degree first.
+fn parse_polynomial(coefficients: &str) -> Result<Polynomial, String> {
+    let coefficients = coefficients
+        .split(',')
+        .map(|a| match a.trim().parse::<f64>() {
+            Ok(a) if a.is_finite() => Ok(a),
+            _ => Err(format!("Invalid coefficient: '{}'", a.trim())),
+        })
+        .collect::<Result<Vec<f64>, _>>()?;
+    let polynomial = Polynomial::from_real(&coefficients);
+    if polynomial.degree() < 2 {
+        return Err("Polynomial must have degree 2 or higher".to_string());
+    }
+    Ok(polynomial)
+}
+
+/// Parses comma-separated lighting parameters: azimuth and elevation in degrees, height and specular.
+fn parse_lighting(lighting: &str) -> Result<Lighting, String> {
+    let values = lighting
+        .split(',')
+        .map(|a| {
+            a.trim()
+                .parse()
+                .map_err(|_| format!("Invalid lighting parameter: '{}'", a.trim()))
+        })
+        .collect::<Result<Vec<f64>, _>>()?;
+    let [azimuth, elevation, height, specular] = values[..] else {
+        return Err("Lighting must have 4 parameters".to_string());
+    };
+    Ok(Lighting {
+        azimuth: azimuth.to_radians(),
+        elevation: elevation.to_radians(),
+        height,
+        specular,
+    })
+}
+
+fn exit_with_buddhabrot_usage() -> ! {
+    eprintln!("Usage: fractals --buddhabrot <buddhabrot|nebulabrot|anti> <output.ppm> [samples]");
+    process::exit(2);
+}
+
+fn exit_with_distance_usage() -> ! {
+    eprintln!("Usage: fractals --distance <boundary|palette> [julia|mandelbrot]");
+    process::exit(2);
+}
+
+fn exit_with_relief_usage() -> ! {
+    eprintln!(
+        "Usage: fractals --relief [julia|mandelbrot] [azimuth,elevation,height,specular] (angles in degrees)"
     );
+    process::exit(2);
+}
+
+fn exit_with_average_usage() -> ! {
+    eprintln!("Usage: fractals --average <stripe|triangle|curvature> [julia|mandelbrot|nova]");
+    process::exit(2);
+}
+
+fn exit_with_export_palette_usage() -> ! {
+    eprintln!("Usage: fractals --export-palette <name> <output.map|output.ggr|output.json>");
+    process::exit(2);
+}
+
+fn exit_with_trap_usage() -> ! {
+    eprintln!("Usage: fractals --trap [julia|mandelbrot] [image.ppm]");
+    process::exit(2);
+}
+
+/// Runs the app with pixels that are either colors, or escape values colored with a palette.
+fn run_app(palettes: Vec<(String, Palette)>, kernel: impl RuleKernel) {
+    let mut app = FractalExplorerApp::new(TITLE, WIDTH, HEIGHT, kernel).with_palettes(palettes);
     app.main_loop();
 }
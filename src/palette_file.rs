@@ -0,0 +1,574 @@
+//! Loading and saving palettes, so that they can be shared without changing the code.
+//!
+//! The format is picked by the file extension:
+//! * `.map` - Fractint color maps, with the red, green and blue components of a color on each line
+//! * `.ggr` - GIMP gradients
+//! * `.json` - gradient stops, like
+//!   `{"space": "oklab", "stops": [{"position": 0, "color": "#000050"}, {"position": 1, "color": "#ffc800"}]}`.
+//!   The color space is one of the `ColorSpace` names and defaults to `srgb`.
+//!   JSON files can also describe a cosine palette with the red, green and blue values of each parameter,
+//!   like `{"cosine": {"a": [0.5, 0.5, 0.5], "b": [0.5, 0.5, 0.5], "c": [1, 1, 1], "d": [0, 0.33, 0.67]}}`.
+
+use crate::color::{hsv, to_hsv, ColorSpace, Cosine, Palette, Rgb};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    f64::consts::{FRAC_PI_2, PI},
+    fmt::Write,
+    fs, io,
+    path::Path,
+};
+
+/// Extensions of the supported palette files.
+pub const EXTENSIONS: [&str; 3] = ["map", "ggr", "json"];
+
+pub fn read(path: &Path) -> io::Result<Palette> {
+    let text = fs::read_to_string(path)?;
+    match extension(path).as_deref() {
+        Some("map") => parse_map(&text),
+        Some("ggr") => parse_ggr(&text),
+        Some("json") => parse_json(&text),
+        _ => Err(unsupported_extension(path)),
+    }
+}
+
+pub fn write(palette: &Palette, path: &Path) -> io::Result<()> {
+    let name = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let text = match extension(path).as_deref() {
+        Some("map") => to_map(palette),
+        Some("ggr") => to_ggr(palette, &name),
+        Some("json") => to_json(palette),
+        _ => return Err(unsupported_extension(path)),
+    };
+    fs::write(path, text)
+}
+
+/// Reads every palette file in `dir`, sorted by name. Palettes are named after their file
+/// without the extension. Files with other extensions are skipped.
+pub fn read_dir(dir: &Path) -> io::Result<Vec<(String, Palette)>> {
+    let mut palettes = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !extension(&path).is_some_and(|e| EXTENSIONS.contains(&e.as_str())) {
+            continue;
+        }
+        let palette = read(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        palettes.push((name, palette));
+    }
+    palettes.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    Ok(palettes)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+fn unsupported_extension(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}: palette files must have one of the extensions {}",
+            path.display(),
+            EXTENSIONS.join(", ")
+        ),
+    )
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Parses a Fractint color map. Anything after the three components of a line is a comment.
+/// Maps usually have 256 colors, but maps of any length are spread over the whole palette.
+pub fn parse_map(text: &str) -> io::Result<Palette> {
+    let colors = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let mut components = line.split_whitespace().map(str::parse);
+            match (components.next(), components.next(), components.next()) {
+                (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok(Rgb(r, g, b)),
+                _ => Err(invalid(format!(
+                    "line {}: expected red, green and blue components between 0 and 255",
+                    i + 1
+                ))),
+            }
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if colors.is_empty() {
+        return Err(invalid("color map has no colors"));
+    }
+    let last = (colors.len() - 1).max(1) as f64;
+    let stops = colors
+        .iter()
+        .enumerate()
+        .map(|(i, &color)| (i as f64 / last, color))
+        .collect_vec();
+    Ok(Palette::from_stops(&stops, ColorSpace::Srgb))
+}
+
+pub fn to_map(palette: &Palette) -> String {
+    palette
+        .colors()
+        .iter()
+        .map(|color| format!("{} {} {}\n", color.0, color.1, color.2))
+        .collect()
+}
+
+/// Parses a GIMP gradient, which is sampled at every palette position. Transparency is ignored.
+pub fn parse_ggr(text: &str) -> io::Result<Palette> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        return Err(invalid("missing 'GIMP Gradient' header"));
+    }
+    let mut line = lines.next();
+    if line.is_some_and(|line| line.starts_with("Name:")) {
+        line = lines.next();
+    }
+    let segment_count: usize = line
+        .and_then(|line| line.parse().ok())
+        .ok_or_else(|| invalid("missing segment count"))?;
+    let segments = lines
+        .map(GgrSegment::parse)
+        .collect::<io::Result<Vec<_>>>()?;
+    if segment_count == 0 || segments.len() != segment_count {
+        return Err(invalid(format!(
+            "expected {} segments, found {}",
+            segment_count,
+            segments.len()
+        )));
+    }
+    Ok(Palette::from_fn(|position| {
+        segments
+            .iter()
+            .find(|segment| position <= segment.right)
+            .unwrap_or(&segments[segments.len() - 1])
+            .color(position)
+    }))
+}
+
+/// Saves the palette as a GIMP gradient with a linear segment between every two palette colors.
+pub fn to_ggr(palette: &Palette, name: &str) -> String {
+    let colors = palette.colors();
+    let segment_count = colors.len() - 1;
+    let mut ggr = format!("GIMP Gradient\nName: {}\n{}\n", name, segment_count);
+    for (i, (start, end)) in colors.iter().tuple_windows().enumerate() {
+        let left = i as f64 / segment_count as f64;
+        let right = (i + 1) as f64 / segment_count as f64;
+        let components = |c: &Rgb| [c.0, c.1, c.2].map(|c| format!("{:.6}", c as f64 / 255.0));
+        writeln!(
+            ggr,
+            "{:.6} {:.6} {:.6} {} 1.000000 {} 1.000000 0 0",
+            left,
+            (left + right) * 0.5,
+            right,
+            components(start).join(" "),
+            components(end).join(" "),
+        )
+        .unwrap();
+    }
+    ggr
+}
+
+/// A segment of a GIMP gradient, which blends two colors between its left and right positions.
+struct GgrSegment {
+    left: f64,
+    /// The position where the blend is halfway between the colors
+    middle: f64,
+    right: f64,
+    start: Rgb,
+    end: Rgb,
+    blending: GgrBlending,
+    coloring: GgrColoring,
+}
+
+/// How GIMP gradient segments go from one color to the other, as a function of the position.
+enum GgrBlending {
+    Linear,
+    Curved,
+    Sine,
+    SphereIncreasing,
+    SphereDecreasing,
+    Step,
+}
+
+/// The color space that GIMP gradient segments blend in.
+enum GgrColoring {
+    Rgb,
+    /// Hues increase from the left color to the right one
+    HsvCounterClockwise,
+    /// Hues decrease from the left color to the right one
+    HsvClockwise,
+}
+
+impl GgrSegment {
+    /// Segments shorter than this are treated as a single point.
+    const EPSILON: f64 = 1e-10;
+
+    /// Parses `left middle right r g b a r g b a blending coloring`, with components in `[0, 1]`.
+    fn parse(line: &str) -> io::Result<Self> {
+        let invalid_segment = || invalid(format!("invalid segment: '{}'", line));
+        let values = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid_segment())?;
+        if values.len() < 13 {
+            return Err(invalid_segment());
+        }
+        let to_rgb = |components: &[f64]| {
+            let [r, g, b] =
+                [0, 1, 2].map(|i| (components[i] * 255.0).round().clamp(0.0, 255.0) as u8);
+            Rgb(r, g, b)
+        };
+        let blending = match values[11] as u32 {
+            0 => GgrBlending::Linear,
+            1 => GgrBlending::Curved,
+            2 => GgrBlending::Sine,
+            3 => GgrBlending::SphereIncreasing,
+            4 => GgrBlending::SphereDecreasing,
+            5 => GgrBlending::Step,
+            _ => return Err(invalid_segment()),
+        };
+        let coloring = match values[12] as u32 {
+            0 => GgrColoring::Rgb,
+            1 => GgrColoring::HsvCounterClockwise,
+            2 => GgrColoring::HsvClockwise,
+            _ => return Err(invalid_segment()),
+        };
+        Ok(Self {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            start: to_rgb(&values[3..6]),
+            end: to_rgb(&values[7..10]),
+            blending,
+            coloring,
+        })
+    }
+
+    fn color(&self, position: f64) -> Rgb {
+        let factor = self.blend(position);
+        match self.coloring {
+            GgrColoring::Rgb => {
+                let lerp = |start: u8, end: u8| {
+                    (start as f64 + (end as f64 - start as f64) * factor).round() as u8
+                };
+                Rgb(
+                    lerp(self.start.0, self.end.0),
+                    lerp(self.start.1, self.end.1),
+                    lerp(self.start.2, self.end.2),
+                )
+            }
+            GgrColoring::HsvCounterClockwise | GgrColoring::HsvClockwise => {
+                let ([h1, s1, v1], [h2, s2, v2]) = (to_hsv(self.start), to_hsv(self.end));
+                let turn = match self.coloring {
+                    GgrColoring::HsvCounterClockwise => (h2 - h1).rem_euclid(1.0),
+                    _ => -(h1 - h2).rem_euclid(1.0),
+                };
+                hsv(
+                    h1 + turn * factor,
+                    s1 + (s2 - s1) * factor,
+                    v1 + (v2 - v1) * factor,
+                )
+            }
+        }
+    }
+
+    /// Returns how far the blend is from the left color to the right one, like GIMP does.
+    fn blend(&self, position: f64) -> f64 {
+        let length = self.right - self.left;
+        let (middle, position) = if length < Self::EPSILON {
+            (0.5, 0.5)
+        } else {
+            (
+                (self.middle - self.left) / length,
+                (position - self.left) / length,
+            )
+        };
+        let linear = || {
+            if position <= middle {
+                if middle < Self::EPSILON {
+                    0.0
+                } else {
+                    0.5 * position / middle
+                }
+            } else if 1.0 - middle < Self::EPSILON {
+                1.0
+            } else {
+                0.5 + 0.5 * (position - middle) / (1.0 - middle)
+            }
+        };
+        match self.blending {
+            GgrBlending::Linear => linear(),
+            GgrBlending::Curved => position.powf(0.5_f64.ln() / middle.max(Self::EPSILON).ln()),
+            GgrBlending::Sine => ((PI * linear() - FRAC_PI_2).sin() + 1.0) * 0.5,
+            GgrBlending::SphereIncreasing => {
+                let position = linear() - 1.0;
+                (1.0 - position * position).sqrt()
+            }
+            GgrBlending::SphereDecreasing => {
+                let position = linear();
+                1.0 - (1.0 - position * position).sqrt()
+            }
+            GgrBlending::Step => {
+                if position >= middle {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// The JSON palette file, with either gradient stops or a cosine palette.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct JsonPalette {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    space: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stops: Option<Vec<JsonStop>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cosine: Option<JsonCosine>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonStop {
+    position: f64,
+    color: String,
+}
+
+/// The red, green and blue values of each cosine palette parameter.
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonCosine {
+    a: [f64; 3],
+    b: [f64; 3],
+    c: [f64; 3],
+    d: [f64; 3],
+}
+
+/// Parses gradient stops or a cosine palette in the JSON format described in the module documentation.
+pub fn parse_json(text: &str) -> io::Result<Palette> {
+    let palette: JsonPalette = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+    if let Some(cosine) = palette.cosine {
+        return Ok(Palette::from_cosine(Cosine {
+            offset: cosine.a,
+            amplitude: cosine.b,
+            frequency: cosine.c,
+            phase: cosine.d,
+        }));
+    }
+    let space = match palette.space {
+        None => ColorSpace::Srgb,
+        Some(name) => ColorSpace::from_name(&name).ok_or_else(|| {
+            invalid(format!(
+                "unknown color space '{}', expected one of {}",
+                name,
+                ColorSpace::ALL.map(ColorSpace::name).join(", ")
+            ))
+        })?,
+    };
+    let Some(stops) = palette.stops else {
+        return Err(invalid("missing 'stops' array"));
+    };
+    let stops = stops
+        .iter()
+        .enumerate()
+        .map(|(i, stop)| {
+            let color = parse_hex_color(&stop.color)
+                .ok_or_else(|| invalid(format!("stop {}: invalid color '{}'", i, stop.color)))?;
+            Ok((stop.position, color))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if stops.is_empty()
+        || stops
+            .iter()
+            .any(|(position, _)| !(0.0..=1.0).contains(position))
+        || stops.windows(2).any(|w| w[0].0 > w[1].0)
+    {
+        return Err(invalid(
+            "stops must have increasing positions between 0 and 1",
+        ));
+    }
+    Ok(Palette::from_stops(&stops, space))
+}
+
+/// Saves every palette color as a stop.
+pub fn to_json(palette: &Palette) -> String {
+    let colors = palette.colors();
+    let last = (colors.len() - 1) as f64;
+    let stops = colors
+        .iter()
+        .enumerate()
+        .map(|(i, color)| JsonStop {
+            position: i as f64 / last,
+            color: format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2),
+        })
+        .collect();
+    let palette = JsonPalette {
+        space: Some(ColorSpace::Srgb.name().to_string()),
+        stops: Some(stops),
+        ..JsonPalette::default()
+    };
+    serde_json::to_string_pretty(&palette).expect("palettes can always be serialized") + "\n"
+}
+
+/// Parses colors like `#ff8000`.
+fn parse_hex_color(color: &str) -> Option<Rgb> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgb(component(0)?, component(2)?, component(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{palettes, ColorSpace, Cosine, Palette, Rgb},
+        palette_file::{self, parse_ggr, parse_json, parse_map, to_ggr, to_json, to_map},
+        rules::MAX_ITERS,
+    };
+    use std::{fs, path::PathBuf};
+
+    fn assert_same_palette(lhs: &Palette, rhs: &Palette) {
+        assert_eq!(lhs.colors(), rhs.colors());
+    }
+
+    #[test]
+    fn palettes_round_trip_through_every_format() {
+        for palette in [&*palettes::RAINBOW_DARK, &*palettes::BLUE_GREEN] {
+            assert_same_palette(&parse_map(&to_map(palette)).unwrap(), palette);
+            assert_same_palette(&parse_ggr(&to_ggr(palette, "test")).unwrap(), palette);
+            assert_same_palette(&parse_json(&to_json(palette)).unwrap(), palette);
+        }
+    }
+
+    #[test]
+    fn short_maps_are_spread_over_the_palette() {
+        let palette = parse_map("0 0 0 black\n\n255 255 255 white\n").unwrap();
+        assert_eq!(palette.color(0), Rgb(0, 0, 0));
+        assert_eq!(palette.color(MAX_ITERS), Rgb(255, 255, 255));
+        assert_eq!(palette.color(51), Rgb(51, 51, 51));
+
+        let error = parse_map("0 0 0\n0 256 0\n").unwrap_err();
+        assert!(error.to_string().starts_with("line 2:"), "{}", error);
+        assert!(parse_map("\n").is_err());
+    }
+
+    #[test]
+    fn ggr_segments_are_blended() {
+        let ggr = "GIMP Gradient\n\
+            Name: Test\n\
+            2\n\
+            0.0 0.25 0.5 0 0 0 1 1 1 1 1 0 0\n\
+            0.5 0.75 1.0 1 0 0 1 0 0 1 1 5 0\n";
+        let palette = parse_ggr(ggr).unwrap();
+        assert_eq!(palette.color(0), Rgb(0, 0, 0));
+        // The middle of the first segment is at a quarter of the palette
+        assert_eq!(palette.color(64), Rgb(128, 128, 128));
+        // The second segment steps from red to blue at its middle
+        assert_eq!(palette.color(180), Rgb(255, 0, 0));
+        assert_eq!(palette.color(200), Rgb(0, 0, 255));
+
+        assert!(parse_ggr("GIMP Gradient\n2\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+        assert!(parse_ggr("Not a gradient\n").is_err());
+    }
+
+    #[test]
+    fn ggr_hues_turn_in_the_given_direction() {
+        // From red to green, counterclockwise through yellow and clockwise through blue
+        let gradient = |coloring| {
+            let ggr = format!("GIMP Gradient\n1\n0 0.5 1 1 0 0 1 0 1 0 1 0 {}\n", coloring);
+            parse_ggr(&ggr).unwrap()
+        };
+        let middle = MAX_ITERS / 2;
+        let counterclockwise = gradient(1).color(middle);
+        let clockwise = gradient(2).color(middle);
+        assert!(
+            counterclockwise.0 > 200 && counterclockwise.2 < 10,
+            "{:?}",
+            counterclockwise
+        );
+        assert!(clockwise.2 > 200 && clockwise.1 < 10, "{:?}", clockwise);
+    }
+
+    #[test]
+    fn json_stops_use_the_color_space() {
+        let json = r##"{
+            "space": "oklab",
+            "stops": [
+                {"position": 0, "color": "#000050"},
+                {"position": 0.5, "color": "#FFC800"},
+                {"position": 1.0, "color": "#000000"}
+            ]
+        }"##;
+        let stops = [
+            (0.0, Rgb(0, 0, 80)),
+            (0.5, Rgb(255, 200, 0)),
+            (1.0, Rgb(0, 0, 0)),
+        ];
+        assert_same_palette(
+            &parse_json(json).unwrap(),
+            &Palette::from_stops(&stops, ColorSpace::Oklab),
+        );
+
+        for invalid in [
+            r##"{"stops": []}"##,
+            r##"{"space": "cmyk", "stops": [{"position": 0, "color": "#000000"}]}"##,
+            r##"{"stops": [{"position": 0.5, "color": "#000000"}, {"position": 0.2, "color": "#000000"}]}"##,
+            r##"{"stops": [{"position": 0, "color": "black"}]}"##,
+            r##"{"stops": [{"position": 0, "color": "#000000"}]"##,
+        ] {
+            assert!(parse_json(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn json_cosine_palettes_have_every_parameter() {
+        let json = r#"{"cosine": {"a": [0.5, 0.5, 0.5], "b": [0.5, 0.5, 0.5], "c": [1, 1, 1], "d": [0, 0.5, 0.25]}}"#;
+        let cosine = Cosine {
+            phase: [0.0, 0.5, 0.25],
+            ..Cosine::DEFAULT
+        };
+        assert_same_palette(&parse_json(json).unwrap(), &Palette::from_cosine(cosine));
+
+        assert!(parse_json(r#"{"cosine": {"a": [0.5, 0.5, 0.5]}}"#).is_err());
+        assert!(parse_json(r#"{"cosine": {"a": [1], "b": [1], "c": [1], "d": [1]}}"#).is_err());
+    }
+
+    #[test]
+    fn palette_directories_are_read_by_extension() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/palette-file-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b.map", "a.ggr", "c.json"] {
+            palette_file::write(&palettes::CYAN, &dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a palette").unwrap();
+        assert!(palette_file::write(&palettes::CYAN, &dir.join("d.png")).is_err());
+
+        let palettes = palette_file::read_dir(&dir).unwrap();
+        assert_eq!(
+            palettes
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+        for (_, palette) in &palettes {
+            assert_same_palette(palette, &palettes::CYAN);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    autodiff::Jet,
+    rules::{self, RootFindingParams},
+    simd::{Array, SimdComplex},
+    utils::Complex,
+};
+use nalgebra::ComplexField;
+use std::f64::consts::TAU;
+
+const ROOT_FINDING_MAX_ITERS: usize = 1000;
+/// Steps are relative to the modulus of the root, or absolute below 1.
+const ROOT_FINDING_TOLERANCE: f64 = 1e-14;
+/// Values within this many rounding errors of 0 are roots up to rounding.
+const ROOT_RESIDUAL_ROUNDING_ERRORS: f64 = 64.0;
+const ROOT_SNAP_TOLERANCE: f64 = 1e-12;
+
+/// Polynomial with complex coefficients, stored from the highest degree down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<Complex>,
+}
+
+impl Polynomial {
+    /// Creates a polynomial from its coefficients, highest degree first.
+    /// Leading zero coefficients are ignored.
+    pub fn new(coefficients: impl IntoIterator<Item = Complex>) -> Self {
+        let mut coefficients: Vec<_> = coefficients
+            .into_iter()
+            .skip_while(|&a| a == Complex::default())
+            .collect();
+        if coefficients.is_empty() {
+            coefficients.push(Complex::default());
+        }
+        Self { coefficients }
+    }
+
+    /// Creates a polynomial from real coefficients, highest degree first.
+    pub fn from_real(coefficients: &[f64]) -> Self {
+        Self::new(coefficients.iter().map(|&a| Complex::new(a, 0.0)))
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    #[allow(dead_code)]
+    pub fn derivative(&self) -> Self {
+        let degree = self.degree();
+        Self::new(
+            self.coefficients[..degree]
+                .iter()
+                .enumerate()
+                .map(|(i, &a)| a * (degree - i) as f64),
+        )
+    }
+
+    /// Evaluates the polynomial on each lane using Horner's scheme.
+    pub fn eval<const N: usize>(&self, z: SimdComplex<N>) -> SimdComplex<N> {
+        self.coefficients
+            .iter()
+            .fold(SimdComplex::default(), |acc, &a| {
+                acc * z + SimdComplex::from_complex(a)
+            })
+    }
+
+    /// Evaluates the polynomial on a jet, to get its derivatives along with its value.
+    pub fn eval_jet<const N: usize, const TERMS: usize>(&self, z: Jet<N, TERMS>) -> Jet<N, TERMS> {
+        self.coefficients
+            .iter()
+            .fold(Jet::constant(SimdComplex::default()), |acc, &a| {
+                acc * z + SimdComplex::from_complex(a)
+            })
+    }
+
+    /// Iterates a root-finding method starting from `z`, returning the iteration count
+    /// and the value each lane converged to. The roots are only found with `c = 0`,
+    /// other values of `c` give the Nova fractal of the method.
+    #[inline(always)]
+    pub fn find_root<const N: usize>(
+        &self,
+        method: RootFindingMethod,
+        params: RootFindingParams,
+        c: SimdComplex<N>,
+        z: SimdComplex<N>,
+    ) -> (Array<u8, N>, SimdComplex<N>) {
+        match method {
+            RootFindingMethod::Newton => {
+                rules::nova_auto_with_root(z, c, params, |z| self.eval_jet(z))
+            }
+            RootFindingMethod::Halley => {
+                rules::halley_with_root(z, c, params, |z| self.eval_jet(z))
+            }
+            RootFindingMethod::Householder3 => {
+                rules::householder_with_root(z, c, params, |z: Jet<N, 4>| self.eval_jet(z))
+            }
+            RootFindingMethod::Schroder => {
+                rules::schroder_with_root(z, c, params, |z| self.eval_jet(z))
+            }
+            RootFindingMethod::Secant => rules::secant_with_root(z, c, params, |z| self.eval(z)),
+        }
+    }
+
+    pub fn eval_scalar(&self, z: Complex) -> Complex {
+        self.coefficients
+            .iter()
+            .fold(Complex::default(), |acc, &a| acc * z + a)
+    }
+
+    /// Finds all roots with the Durand-Kerner method, so there are exactly `degree` of them
+    /// (counting multiplicity). Roots are sorted by argument, then by modulus.
+    /// Iteration stops once the steps are negligible or every root is one up to rounding,
+    /// since multiple roots converge slowly and then stall at the rounding error.
+    /// Fails if the roots are not finite, which can happen with extreme coefficients.
+    pub fn roots(&self) -> Result<Vec<Complex>, String> {
+        let degree = self.degree();
+        let leading = self.coefficients[0];
+        // Powers of a number that is neither real nor a root of unity are good starting points
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<_> = (0..degree).map(|k| seed.powi(k as i32)).collect();
+        for _ in 0..ROOT_FINDING_MAX_ITERS {
+            let mut converged = true;
+            for i in 0..degree {
+                let denominator = (0..degree)
+                    .filter(|&j| j != i)
+                    .fold(leading, |acc, j| acc * (roots[i] - roots[j]));
+                let change = self.eval_scalar(roots[i]) / denominator;
+                roots[i] -= change;
+                converged &=
+                    change.modulus() <= ROOT_FINDING_TOLERANCE * roots[i].modulus().max(1.0);
+            }
+            if converged || roots.iter().all(|&root| self.is_root_up_to_rounding(root)) {
+                break;
+            }
+        }
+        if roots
+            .iter()
+            .any(|root| !root.re.is_finite() || !root.im.is_finite())
+        {
+            return Err("Cannot find the roots of the polynomial".to_string());
+        }
+        // Snap parts that are zero up to rounding, so that the order below is stable
+        for root in &mut roots {
+            let scale = root.modulus().max(1.0) * ROOT_SNAP_TOLERANCE;
+            if root.re.abs() < scale {
+                root.re = 0.0;
+            }
+            if root.im.abs() < scale {
+                root.im = 0.0;
+            }
+        }
+        roots.sort_by(|a, b| {
+            let argument = |z: &Complex| z.argument().rem_euclid(TAU);
+            argument(a)
+                .total_cmp(&argument(b))
+                .then(a.modulus().total_cmp(&b.modulus()))
+        });
+        Ok(roots)
+    }
+
+    /// Whether `p(z)` is 0 up to the rounding error of evaluating it with Horner's scheme.
+    fn is_root_up_to_rounding(&self, z: Complex) -> bool {
+        let modulus = z.modulus();
+        let magnitude = self
+            .coefficients
+            .iter()
+            .fold(0.0, |acc, a| acc * modulus + a.modulus());
+        self.eval_scalar(z).modulus() <= ROOT_RESIDUAL_ROUNDING_ERRORS * f64::EPSILON * magnitude
+    }
+}
+
+/// Root-finding methods that can be used to render a polynomial's basins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootFindingMethod {
+    Newton,
+    Halley,
+    /// Householder's method of order 3.
+    Householder3,
+    Schroder,
+    Secant,
+}
+
+impl RootFindingMethod {
+    pub const ALL: [RootFindingMethod; 5] = [
+        RootFindingMethod::Newton,
+        RootFindingMethod::Halley,
+        RootFindingMethod::Householder3,
+        RootFindingMethod::Schroder,
+        RootFindingMethod::Secant,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RootFindingMethod::Newton => "newton",
+            RootFindingMethod::Halley => "halley",
+            RootFindingMethod::Householder3 => "householder3",
+            RootFindingMethod::Schroder => "schroder",
+            RootFindingMethod::Secant => "secant",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|method| method.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        autodiff::Jet,
+        polynomial::{Polynomial, RootFindingMethod},
+        rules::RootFindingParams,
+        simd::SimdComplex,
+        utils::Complex,
+    };
+    use nalgebra::ComplexField;
+
+    const TOLERANCE: f64 = 1e-12;
+
+    #[test]
+    fn leading_zeros_are_ignored() {
+        let p = Polynomial::from_real(&[0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(p.degree(), 1);
+        assert_eq!(Polynomial::from_real(&[0.0]).degree(), 0);
+    }
+
+    #[test]
+    fn eval_matches_scalar() {
+        let p = Polynomial::new([
+            Complex::new(1.0, 0.5),
+            Complex::new(-2.0, 0.0),
+            Complex::new(0.0, 3.0),
+            Complex::new(4.0, -1.0),
+        ]);
+        let zs = [
+            Complex::new(0.5, -1.5),
+            Complex::new(-2.0, 0.25),
+            Complex::new(0.0, 0.0),
+            Complex::new(3.0, 1.0),
+        ];
+        let values = p.eval(SimdComplex::<4> {
+            re: zs.map(|z| z.re).into(),
+            im: zs.map(|z| z.im).into(),
+        });
+        for (i, z) in zs.into_iter().enumerate() {
+            let expected = p.eval_scalar(z);
+            assert_eq!((values.re[i], values.im[i]), (expected.re, expected.im));
+        }
+    }
+
+    #[test]
+    fn jet_derivatives_match_derivative_polynomial() {
+        let p = Polynomial::from_real(&[2.0, -3.0, 0.5, 5.0]);
+        let z = SimdComplex::<2>::splat(0.75, -1.25);
+        let [_, df, d2f] = p.eval_jet(Jet::<2, 3>::variable(z)).derivatives();
+        let expected_df = p.derivative().eval(z);
+        let expected_d2f = p.derivative().derivative().eval(z);
+        assert!((df - expected_df).norm_squared()[0] < TOLERANCE);
+        assert!((d2f - expected_d2f).norm_squared()[0] < TOLERANCE);
+    }
+
+    #[test]
+    fn all_methods_find_roots() {
+        let p = Polynomial::from_real(&[1.0, 0.0, 0.0, -1.0]);
+        let z = SimdComplex::<4>::splat(0.9, 0.2);
+        for method in RootFindingMethod::ALL {
+            let (_, root) = p.find_root(
+                method,
+                RootFindingParams::DEFAULT,
+                SimdComplex::default(),
+                z,
+            );
+            assert!(
+                (root - SimdComplex::splat(1.0, 0.0)).norm_squared()[0] < 1e-8,
+                "{} did not converge",
+                method.name()
+            );
+            assert_eq!(RootFindingMethod::from_name(method.name()), Some(method));
+        }
+    }
+
+    #[test]
+    fn derivative_works() {
+        let p = Polynomial::from_real(&[2.0, -3.0, 0.0, 5.0]);
+        assert_eq!(p.derivative(), Polynomial::from_real(&[6.0, -6.0, 0.0]));
+        assert_eq!(
+            Polynomial::from_real(&[5.0]).derivative(),
+            Polynomial::from_real(&[0.0])
+        );
+    }
+
+    #[test]
+    fn roots_of_unity_are_found() {
+        let roots = Polynomial::from_real(&[1.0, 0.0, 0.0, -1.0])
+            .roots()
+            .unwrap();
+        let angle = std::f64::consts::TAU / 3.0;
+        let expected = [
+            Complex::new(1.0, 0.0),
+            Complex::new(angle.cos(), angle.sin()),
+            Complex::new((2.0 * angle).cos(), (2.0 * angle).sin()),
+        ];
+        assert_roots_eq(&roots, &expected);
+    }
+
+    #[test]
+    fn roots_of_factored_polynomial_are_found() {
+        // (z - 1)(z - 2)(z + 3i)(z + 1 - i)
+        let expected = [
+            Complex::new(1.0, 0.0),
+            Complex::new(2.0, 0.0),
+            Complex::new(0.0, -3.0),
+            Complex::new(-1.0, 1.0),
+        ];
+        let p = expected
+            .iter()
+            .fold(Polynomial::from_real(&[1.0]), |p, &r| {
+                multiply_by_linear(&p, r)
+            });
+        let mut roots = p.roots().unwrap();
+        assert_eq!(roots.len(), p.degree());
+        roots.sort_by(|a, b| (a.re, a.im).partial_cmp(&(b.re, b.im)).unwrap());
+        let mut expected = expected.to_vec();
+        expected.sort_by(|a, b| (a.re, a.im).partial_cmp(&(b.re, b.im)).unwrap());
+        assert_roots_eq(&roots, &expected);
+    }
+
+    #[test]
+    fn extreme_coefficients_are_reported() {
+        for coefficients in [
+            [1.0, 0.0, 0.0, -1e300],
+            [0.0, 1e-300, 0.0, 1.0],
+            [0.0, 1.0, f64::NAN, -1.0],
+        ] {
+            let p = Polynomial::from_real(&coefficients);
+            assert!(p.roots().is_err(), "{:?}", coefficients);
+        }
+    }
+
+    #[test]
+    fn multiple_roots_are_found() {
+        // (z - 1)^4 and (z - 1)^3 (z + 2)
+        for (coefficients, roots) in [
+            (&[1.0, -4.0, 6.0, -4.0, 1.0][..], &[1.0, 1.0, 1.0, 1.0][..]),
+            (
+                &[1.0, -1.0, -3.0, 5.0, -2.0][..],
+                &[1.0, 1.0, 1.0, -2.0][..],
+            ),
+        ] {
+            let mut found = Polynomial::from_real(coefficients).roots().unwrap();
+            // Copies of a multiple root are only close to it, so their arguments are unordered
+            found.sort_by(|a, b| b.re.total_cmp(&a.re));
+            assert_eq!(found.len(), roots.len());
+            for (found, &root) in found.iter().zip(roots) {
+                assert!(
+                    (*found - root).modulus() < 1e-3,
+                    "{:?}: {} != {}",
+                    coefficients,
+                    found,
+                    root
+                );
+            }
+        }
+    }
+
+    /// Returns `p(z) * (z - root)`.
+    fn multiply_by_linear(p: &Polynomial, root: Complex) -> Polynomial {
+        let mut coefficients = p.coefficients.clone();
+        coefficients.push(Complex::default());
+        for i in (1..coefficients.len()).rev() {
+            let previous = coefficients[i - 1];
+            coefficients[i] -= previous * root;
+        }
+        Polynomial::new(coefficients)
+    }
+
+    fn assert_roots_eq(actual: &[Complex], expected: &[Complex]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).modulus() < TOLERANCE,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+}
@@ -1,24 +1,88 @@
 use crate::{
-    color::Rgb,
+    cpu::SimdLevel,
     simd::{Array, SimdComplex},
     utils::{Complex, FnSync},
     view::ComplexPlaneView,
 };
 use itertools::Itertools;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
-use std::{num::NonZeroUsize, ops::Range, usize};
+use std::{iter::Flatten, num::NonZeroUsize, ops::Range, vec};
+
+/// Computes the values of pixels from their points in the Complex plane, for any SIMD lane
+/// count, so that the renderer can pick the lane count of its SIMD level.
+pub trait Kernel: Sync {
+    type Output: Send;
+
+    fn compute<const N: usize>(&self, z: SimdComplex<N>) -> Array<Self::Output, N>;
+}
+
+/// A kernel for a single lane count, which is what the renderer runs.
+trait LaneKernel<const N: usize>: Sync {
+    type Output: Send;
+
+    fn compute(&self, z: SimdComplex<N>) -> Array<Self::Output, N>;
+}
+
+impl<K: Kernel, const N: usize> LaneKernel<N> for K {
+    type Output = K::Output;
+
+    #[inline(always)]
+    fn compute(&self, z: SimdComplex<N>) -> Array<Self::Output, N> {
+        Kernel::compute(self, z)
+    }
+}
+
+/// A closure that only runs with the lane count of its argument.
+struct FixedLanes<F>(F);
+
+impl<F, T, const N: usize> LaneKernel<N> for FixedLanes<F>
+where
+    F: FnSync(SimdComplex<N>) -> Array<T, N>,
+    T: Send,
+{
+    type Output = T;
+
+    #[inline(always)]
+    fn compute(&self, z: SimdComplex<N>) -> Array<T, N> {
+        (self.0)(z)
+    }
+}
 
 pub struct Renderer {
     chunk_count: usize,
+    simd_level: SimdLevel,
 }
 
 impl Renderer {
     const DEFAULT_CHUNK_COUNT: usize = 16;
 
     pub fn new() -> Self {
-        Self {
+        Self::with_simd_level(SimdLevel::detect())
+            .expect("The detected SIMD level should be supported by this CPU")
+    }
+
+    /// Creates a renderer that uses kernels compiled for the given level,
+    /// or `None` if the level is not supported by the current CPU.
+    pub fn with_simd_level(simd_level: SimdLevel) -> Option<Self> {
+        SimdLevel::supported().contains(&simd_level).then(|| Self {
             chunk_count: Self::resolve_chunk_count(),
-        }
+            simd_level,
+        })
+    }
+
+    /// Overrides the number of chunks that each frame is split into.
+    pub fn with_chunk_count(mut self, chunk_count: usize) -> Self {
+        assert!(chunk_count > 0, "Chunk count must be positive");
+        self.chunk_count = chunk_count;
+        self
+    }
+
+    pub fn simd_level(&self) -> SimdLevel {
+        self.simd_level
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
     }
 
     fn resolve_chunk_count() -> usize {
@@ -27,38 +91,134 @@ impl Renderer {
             .unwrap_or(Self::DEFAULT_CHUNK_COUNT)
     }
 
-    pub fn render<F>(&self, view: &ComplexPlaneView, color_computer: F) -> impl Iterator<Item = Rgb>
+    /// Number of SIMD lanes that kernels run with, which depends on the SIMD level.
+    pub fn lanes(&self) -> usize {
+        self.simd_level.register_lanes()
+    }
+
+    /// Renders a frame, running the kernel with as many lanes as fit in a vector register
+    /// at the SIMD level of the renderer.
+    /// Pixels are usually colors, but they can be any value that is colored later.
+    pub fn render<K: Kernel>(
+        &self,
+        view: &ComplexPlaneView,
+        kernel: &K,
+    ) -> impl Iterator<Item = K::Output> {
+        self.render_chunks(view, |chunk_rows| match self.simd_level {
+            SimdLevel::Scalar => self.dispatch::<_, 1>(chunk_rows, view, kernel),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Sse2 => self.dispatch::<_, 2>(chunk_rows, view, kernel),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Avx2 => self.dispatch::<_, 4>(chunk_rows, view, kernel),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Avx512 => self.dispatch::<_, 8>(chunk_rows, view, kernel),
+        })
+    }
+
+    /// Renders a frame with the lane count `N` of `color_computer`, instead of the one of the
+    /// SIMD level. The kernel is still compiled for the SIMD level.
+    pub fn render_with_lanes<F, T, const N: usize>(
+        &self,
+        view: &ComplexPlaneView,
+        color_computer: F,
+    ) -> impl Iterator<Item = T>
     where
-        F: FnSync(SimdComplex) -> Array<Rgb>,
+        F: FnSync(SimdComplex<N>) -> Array<T, N>,
+        T: Send,
     {
+        let kernel = FixedLanes(color_computer);
+        self.render_chunks(view, |chunk_rows| {
+            self.dispatch::<_, N>(chunk_rows, view, &kernel)
+        })
+    }
+
+    /// Renders a chunk of rows with the kernel compiled for the SIMD level of the renderer.
+    fn dispatch<K: LaneKernel<N>, const N: usize>(
+        &self,
+        chunk_rows: Range<usize>,
+        view: &ComplexPlaneView,
+        kernel: &K,
+    ) -> Vec<K::Output> {
+        // SAFETY: the constructor guarantees that the CPU supports `self.simd_level`.
+        match self.simd_level {
+            SimdLevel::Scalar => Self::render_chunk::<K, N>(chunk_rows, view, kernel),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Sse2 => unsafe { Self::render_chunk_sse2::<K, N>(chunk_rows, view, kernel) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Avx2 => unsafe { Self::render_chunk_avx2::<K, N>(chunk_rows, view, kernel) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Avx512 => unsafe {
+                Self::render_chunk_avx512::<K, N>(chunk_rows, view, kernel)
+            },
+        }
+    }
+
+    /// Splits the frame into chunks of rows that are rendered in parallel, in order.
+    fn render_chunks<T: Send>(
+        &self,
+        view: &ComplexPlaneView,
+        render_chunk: impl Fn(Range<usize>) -> Vec<T> + Sync,
+    ) -> Flatten<vec::IntoIter<Vec<T>>> {
         let view_height = view.height();
         let chunk_height = view_height.div_ceil(self.chunk_count);
 
         let mut chunks: Vec<_> = Vec::with_capacity(self.chunk_count);
         (0..view_height)
             .step_by(chunk_height)
-            // Collecting here before converting to a parallel iterator allows 
+            // Collecting here before converting to a parallel iterator allows
             // using the order-preserving `collect_into_vec` later.
             // Otherwise, we would need to sort the chunks by row index.
             .collect_vec()
             .into_par_iter()
-            .map(move |chunk_start| {
+            .map(|chunk_start| {
                 let chunk_end = (chunk_start + chunk_height).min(view_height);
-                self.simd_render_chunk(chunk_start..chunk_end, view, &color_computer)
+                render_chunk(chunk_start..chunk_end)
             })
             .collect_into_vec(&mut chunks);
         chunks.into_iter().flatten()
     }
 
-    fn simd_render_chunk<F>(
-        &self,
+    // The kernel and the lane count are generic parameters of the functions below, so that
+    // each SIMD level gets its own copy of the kernel, compiled with the level's features.
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[target_feature(enable = "sse2")]
+    unsafe fn render_chunk_sse2<K: LaneKernel<N>, const N: usize>(
         chunk_rows: Range<usize>,
         view: &ComplexPlaneView,
-        color_computer: F,
-    ) -> Vec<Rgb>
-    where
-        F: FnSync(SimdComplex) -> Array<Rgb>,
-    {
+        kernel: &K,
+    ) -> Vec<K::Output> {
+        Self::render_chunk::<K, N>(chunk_rows, view, kernel)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn render_chunk_avx2<K: LaneKernel<N>, const N: usize>(
+        chunk_rows: Range<usize>,
+        view: &ComplexPlaneView,
+        kernel: &K,
+    ) -> Vec<K::Output> {
+        Self::render_chunk::<K, N>(chunk_rows, view, kernel)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn render_chunk_avx512<K: LaneKernel<N>, const N: usize>(
+        chunk_rows: Range<usize>,
+        view: &ComplexPlaneView,
+        kernel: &K,
+    ) -> Vec<K::Output> {
+        Self::render_chunk::<K, N>(chunk_rows, view, kernel)
+    }
+
+    /// Renders a chunk of rows. Always inlined into the functions above, so that
+    /// the kernel can be inlined as well and compiled with their target features.
+    #[inline(always)]
+    fn render_chunk<K: LaneKernel<N>, const N: usize>(
+        chunk_rows: Range<usize>,
+        view: &ComplexPlaneView,
+        kernel: &K,
+    ) -> Vec<K::Output> {
         let view_width = view.width();
         let chunk_height = chunk_rows.end - chunk_rows.start;
         let chunk_size = chunk_height * view_width;
@@ -66,15 +226,17 @@ impl Renderer {
         chunk_rows
             .cartesian_product(0..view_width)
             .map(|(y, x)| pixel_to_complex(x, y))
-            .chunks(SimdComplex::LEN)
+            .chunks(SimdComplex::<N>::LEN)
             .into_iter()
-            .map(|chunk| Self::chunk_to_simd_complex(chunk))
-            .flat_map(color_computer)
+            .map(Self::chunk_to_simd_complex)
+            .flat_map(|z| kernel.compute(z))
             .take(chunk_size)
             .collect_vec()
     }
 
-    fn chunk_to_simd_complex(chunk: impl Iterator<Item = Complex>) -> SimdComplex {
+    fn chunk_to_simd_complex<const N: usize>(
+        chunk: impl Iterator<Item = Complex>,
+    ) -> SimdComplex<N> {
         let mut res = SimdComplex::default();
         for (i, z) in chunk.enumerate() {
             res.re[i] = z.re;
@@ -83,3 +245,101 @@ impl Renderer {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{palettes, Rgb},
+        cpu::SimdLevel,
+        render::{Kernel, Renderer},
+        rules::{self, Bailout},
+        simd::{Array, SimdComplex},
+        view::ComplexPlaneView,
+    };
+    use itertools::Itertools;
+
+    struct JuliaKernel;
+
+    impl Kernel for JuliaKernel {
+        type Output = Rgb;
+
+        fn compute<const N: usize>(&self, z: SimdComplex<N>) -> Array<Rgb, N> {
+            let c = SimdComplex::splat(-0.75, 0.2);
+            palettes::BLUE_GREEN.color_array(rules::julia(z, c, Bailout::DEFAULT))
+        }
+    }
+
+    /// Fills every pixel with the lane count it was computed with.
+    struct LaneCountKernel;
+
+    impl Kernel for LaneCountKernel {
+        type Output = usize;
+
+        fn compute<const N: usize>(&self, _: SimdComplex<N>) -> Array<usize, N> {
+            [N; N]
+        }
+    }
+
+    #[test]
+    fn all_supported_levels_render_the_same_frame() {
+        let view = ComplexPlaneView::new(67, 41);
+        let render = |level| {
+            Renderer::with_simd_level(level)
+                .unwrap()
+                .render(&view, &JuliaKernel)
+                .collect_vec()
+        };
+        let expected = render(SimdLevel::Scalar);
+        assert_eq!(expected.len(), view.width() * view.height());
+        for level in SimdLevel::supported() {
+            assert_eq!(
+                render(level),
+                expected,
+                "{} output differs from scalar",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn kernels_run_with_the_lanes_of_the_level() {
+        let view = ComplexPlaneView::new(13, 7);
+        let lanes = SimdLevel::supported()
+            .into_iter()
+            .map(|level| {
+                let renderer = Renderer::with_simd_level(level).unwrap();
+                let pixels = renderer.render(&view, &LaneCountKernel).collect_vec();
+                assert_eq!(pixels.len(), view.width() * view.height());
+                assert!(pixels.iter().all(|&lanes| lanes == renderer.lanes()));
+                renderer.lanes()
+            })
+            .collect_vec();
+        assert_eq!(
+            lanes[0], 1,
+            "the scalar level should run with a single lane"
+        );
+        assert!(lanes.windows(2).all(|w| w[0] < w[1]), "{:?}", lanes);
+    }
+
+    #[test]
+    fn all_lane_counts_render_the_same_frame() {
+        fn render<const N: usize>(view: &ComplexPlaneView) -> Vec<Rgb> {
+            Renderer::new()
+                .render_with_lanes(view, |z: SimdComplex<N>| {
+                    palettes::BLUE_GREEN.color_array(rules::julia(
+                        z,
+                        SimdComplex::splat(-0.75, 0.2),
+                        Bailout::DEFAULT,
+                    ))
+                })
+                .collect_vec()
+        }
+
+        let view = ComplexPlaneView::new(67, 41);
+        let expected = render::<1>(&view);
+        assert_eq!(render::<2>(&view), expected);
+        assert_eq!(render::<4>(&view), expected);
+        assert_eq!(render::<8>(&view), expected);
+        assert_eq!(render::<16>(&view), expected);
+    }
+}
@@ -1,53 +1,2416 @@
-use crate::simd::{Array, SimdComplex, SimdCounter, SimdDouble, SIMD_LEN};
-use std::simd::cmp::SimdPartialOrd;
+//! Escape-time rules, generic over the SIMD lane count. They are always inlined so
+//! that they get compiled for the target features of the renderer's multiversioned
+//! chunk kernels.
+
+use crate::{
+    autodiff::{Dual, Jet},
+    formula::Formula,
+    simd::{Array, SimdComplex, SimdCounter, SimdDouble},
+    utils::Complex,
+};
+use std::simd::{cmp::SimdPartialOrd, num::SimdFloat, Mask, Select, StdFloat};
 
 pub const MAX_ITERS: u8 = u8::MAX;
 
-const EPSILON: SimdDouble = SimdDouble::from_array([1e-10; SIMD_LEN]);
-const ESCAPE_RADIUS_SQUARED: SimdDouble = SimdDouble::from_array([4.0; SIMD_LEN]);
+/// Distance between the two starting points of the secant method.
+const SECANT_INITIAL_OFFSET: f64 = 1e-3;
+/// Magnet orbits either escape or converge to the fixed point 1, so they need a larger radius.
+const MAGNET_ESCAPE_RADIUS_SQUARED: f64 = 100.0;
+const MAGNET_CONVERGENCE_RADIUS_SQUARED: f64 = 1e-6;
+/// Distance estimates and surface normals are only accurate once `|z|` is large,
+/// so the rules that track the derivative use a much larger radius.
+const DERIVATIVE_ESCAPE_RADIUS_SQUARED: f64 = 1e8;
+
+/// Rule parameters that can be adjusted at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuleParams {
+    pub seed: Complex,
+    pub exponent: Complex,
+    pub root_finding: RootFindingParams,
+    pub bailout: Bailout,
+    pub trap: OrbitTrap,
+    /// Size of a pixel in the Complex plane, which distance-based coloring is relative to.
+    /// It is not adjustable by itself, the app keeps it in sync with the view.
+    pub pixel_size: f64,
+}
+
+/// Decides when the orbit of an escape-time rule has escaped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bailout {
+    pub radius: f64,
+    pub test: BailoutTest,
+}
+
+/// The quantity compared against the bailout radius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BailoutTest {
+    /// `|z|`, the usual test
+    Norm,
+    /// `|Re z|`
+    Real,
+    /// `|Im z|`
+    Imaginary,
+    /// `|Re z| + |Im z|`
+    Manhattan,
+    /// `min(|Re z|, |Im z|)`
+    Min,
+    /// `max(|Re z|, |Im z|)`
+    Max,
+}
+
+impl Bailout {
+    pub const DEFAULT: Self = Self {
+        radius: 2.0,
+        test: BailoutTest::Norm,
+    };
+
+    /// Returns the lanes that have not escaped yet.
+    #[inline(always)]
+    fn is_bounded<const N: usize>(&self, z: SimdComplex<N>) -> Mask<i64, N> {
+        let radius = SimdDouble::splat(self.radius);
+        match self.test {
+            BailoutTest::Norm => z
+                .norm_squared()
+                .simd_lt(SimdDouble::splat(self.radius * self.radius)),
+            BailoutTest::Real => z.re.abs().simd_lt(radius),
+            BailoutTest::Imaginary => z.im.abs().simd_lt(radius),
+            BailoutTest::Manhattan => (z.re.abs() + z.im.abs()).simd_lt(radius),
+            BailoutTest::Min => z.re.abs().simd_min(z.im.abs()).simd_lt(radius),
+            BailoutTest::Max => z.re.abs().simd_max(z.im.abs()).simd_lt(radius),
+        }
+    }
+}
+
+impl Default for Bailout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl BailoutTest {
+    pub const ALL: [Self; 6] = [
+        Self::Norm,
+        Self::Real,
+        Self::Imaginary,
+        Self::Manhattan,
+        Self::Min,
+        Self::Max,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Norm => "norm",
+            Self::Real => "real",
+            Self::Imaginary => "imaginary",
+            Self::Manhattan => "manhattan",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+/// Parameters of the root-finding rules, which iterate `z - a * step(z) + c`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RootFindingParams {
+    /// The relaxation factor `a`.
+    pub relaxation: Complex,
+    /// Iteration stops once a step moves `z` by less than this distance.
+    pub tolerance: f64,
+    /// Iteration stops once `|z|` grows beyond this value. Infinite by default.
+    pub bailout: f64,
+}
+
+impl RootFindingParams {
+    pub const DEFAULT: Self = Self {
+        relaxation: Complex::new(1.0, 0.0),
+        tolerance: 1e-5,
+        bailout: f64::INFINITY,
+    };
+
+    /// Returns the lanes that moved by more than the tolerance without diverging.
+    #[inline(always)]
+    fn keeps_iterating<const N: usize>(
+        &self,
+        z: SimdComplex<N>,
+        z_next: SimdComplex<N>,
+    ) -> Mask<i64, N> {
+        let tolerance_squared = SimdDouble::splat(self.tolerance * self.tolerance);
+        (z_next - z).norm_squared().simd_ge(tolerance_squared) & self.is_bounded(z_next)
+    }
+
+    /// Returns the lanes where `|z|` is within the bailout.
+    #[inline(always)]
+    fn is_bounded<const N: usize>(&self, z: SimdComplex<N>) -> Mask<i64, N> {
+        let bailout_squared = SimdDouble::splat(self.bailout * self.bailout);
+        z.norm_squared().simd_le(bailout_squared)
+    }
+}
+
+impl Default for RootFindingParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A shape in the Complex plane that orbits are measured against. Orbit-trap rules record
+/// the smallest distance between the orbit and the trap, instead of counting iterations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitTrap {
+    pub shape: TrapShape,
+    pub center: Complex,
+    /// Radius of the `Circle` trap and side of the `Image` trap.
+    pub size: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapShape {
+    Point,
+    /// The horizontal line through the center
+    Line,
+    /// The horizontal and vertical lines through the center
+    Cross,
+    Circle,
+    /// A square where an image is drawn. Points inside it have a distance of 0,
+    /// so the orbit is trapped by the first point that falls inside.
+    Image,
+}
+
+impl OrbitTrap {
+    pub const DEFAULT: Self = Self {
+        shape: TrapShape::Point,
+        center: Complex::new(0.0, 0.0),
+        size: 0.5,
+    };
+
+    /// Returns the distance from each lane of `z` to the trap.
+    #[inline(always)]
+    fn distance<const N: usize>(&self, z: SimdComplex<N>) -> SimdDouble<N> {
+        let z = z - SimdComplex::from_complex(self.center);
+        match self.shape {
+            TrapShape::Point => z.norm_squared().sqrt(),
+            TrapShape::Line => z.im.abs(),
+            TrapShape::Cross => z.re.abs().simd_min(z.im.abs()),
+            TrapShape::Circle => (z.norm_squared().sqrt() - SimdDouble::splat(self.size)).abs(),
+            TrapShape::Image => (z.re.abs().simd_max(z.im.abs())
+                - SimdDouble::splat(self.size * 0.5))
+            .simd_max(SimdDouble::splat(0.0)),
+        }
+    }
+}
+
+impl Default for OrbitTrap {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A statistic of each step of an orbit, which the averaging rules average over the whole orbit.
+/// Every statistic is between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrbitAverage {
+    /// `sin(density * arg z) / 2 + 1/2`, which draws stripes along the field lines.
+    Stripe { density: f64 },
+    /// Where `|z|` falls between the bounds `||z_prev|^2 - |c||` and `|z_prev|^2 + |c|`
+    /// given by the triangle inequality. Only meaningful for `z^2 + c`.
+    TriangleInequality,
+    /// `|arg((z - z_prev) / (z_prev - z_prev_prev))| / pi`, how sharply the orbit turns.
+    Curvature,
+}
+
+impl TrapShape {
+    pub const ALL: [Self; 5] = [
+        Self::Point,
+        Self::Line,
+        Self::Cross,
+        Self::Circle,
+        Self::Image,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Point => "point",
+            Self::Line => "line",
+            Self::Cross => "cross",
+            Self::Circle => "circle",
+            Self::Image => "image",
+        }
+    }
+}
+
+/// Counts the iterations of `step` until `z` escapes.
+#[inline(always)]
+fn escape_time<const N: usize>(
+    z: SimdComplex<N>,
+    bailout: Bailout,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    escape_time_while(z, |z| bailout.is_bounded(z), step)
+}
+
+/// Counts the iterations of `step` until `keep_iterating` is false for `z`.
+#[inline(always)]
+fn escape_time_while<const N: usize>(
+    z: SimdComplex<N>,
+    keep_iterating: impl Fn(SimdComplex<N>) -> Mask<i64, N>,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    escape_time_while_with_hook(z, keep_iterating, step, |_, _, _| ()).0
+}
+
+/// Counts the iterations of `step` until `z` stops moving or diverges, and returns its final value.
+/// Lanes stop updating once they are done, so the value is not affected by other lanes.
+#[inline(always)]
+fn convergence_time<const N: usize>(
+    z: SimdComplex<N>,
+    params: RootFindingParams,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    convergence_time_with_hook(z, params, step, |_, _, _| ())
+}
+
+/// Same as `convergence_time`, but also calls `hook` with the lanes that are still active,
+/// `z` and its next value after every step. Lanes that converge end on the value of the step
+/// that converged, which is the closest to the root, while lanes that diverge keep the last
+/// value within the bailout.
+#[inline(always)]
+fn convergence_time_with_hook<const N: usize>(
+    mut z: SimdComplex<N>,
+    params: RootFindingParams,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    mut hook: impl FnMut(Mask<i64, N>, SimdComplex<N>, SimdComplex<N>),
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let mut cnt = SimdCounter::new();
+    for _ in 0..MAX_ITERS {
+        let z_next = step(z);
+        let was_active = cnt.active();
+        cnt.increment_where(params.keeps_iterating(z, z_next));
+        let converged = was_active & !cnt.active() & params.is_bounded(z_next);
+        if cnt.modified() {
+            hook(cnt.active(), z, z_next);
+        }
+        z = SimdComplex::select(cnt.active() | converged, z_next, z);
+        if !cnt.modified() {
+            break;
+        }
+    }
+    (cnt.counts(), z)
+}
+
+/// Same as `escape_time_while`, but also calls `hook` like `convergence_time_with_hook`,
+/// and returns the final value of `z`. Lanes stop updating once they escape.
+#[inline(always)]
+fn escape_time_while_with_hook<const N: usize>(
+    mut z: SimdComplex<N>,
+    keep_iterating: impl Fn(SimdComplex<N>) -> Mask<i64, N>,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    mut hook: impl FnMut(Mask<i64, N>, SimdComplex<N>, SimdComplex<N>),
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let mut cnt = SimdCounter::new();
+    for _ in 0..MAX_ITERS {
+        cnt.increment_where(keep_iterating(z));
+        if !cnt.modified() {
+            break;
+        }
+        let z_next = step(z);
+        hook(cnt.active(), z, z_next);
+        z = SimdComplex::select(cnt.active(), z_next, z);
+    }
+    (cnt.counts(), z)
+}
+
+/// Sums an `OrbitAverage` over the steps of each lane.
+/// Steps where the statistic is undefined, like the first steps of `Curvature`, are skipped.
+struct AverageAccumulator<const N: usize> {
+    average: OrbitAverage,
+    c: SimdComplex<N>,
+    /// The value of `z` before the last step
+    z_prev: SimdComplex<N>,
+    sum: SimdDouble<N>,
+    /// The last statistic added to the sum
+    last: SimdDouble<N>,
+    terms: SimdDouble<N>,
+}
+
+impl<const N: usize> AverageAccumulator<N> {
+    #[inline(always)]
+    fn new(average: OrbitAverage, c: SimdComplex<N>) -> Self {
+        Self {
+            average,
+            c,
+            z_prev: SimdComplex::splat(f64::NAN, f64::NAN),
+            sum: SimdDouble::splat(0.0),
+            last: SimdDouble::splat(0.0),
+            terms: SimdDouble::splat(0.0),
+        }
+    }
+
+    #[inline(always)]
+    fn add_step(&mut self, active: Mask<i64, N>, z: SimdComplex<N>, z_next: SimdComplex<N>) {
+        let statistic = match self.average {
+            OrbitAverage::Stripe { density } => {
+                (z_next.arg() * SimdDouble::splat(density)).sin() * SimdDouble::splat(0.5)
+                    + SimdDouble::splat(0.5)
+            }
+            OrbitAverage::TriangleInequality => {
+                let z_norm_squared = z.norm_squared();
+                let c_norm = self.c.norm_squared().sqrt();
+                let lower = (z_norm_squared - c_norm).abs();
+                let upper = z_norm_squared + c_norm;
+                (z_next.norm_squared().sqrt() - lower) / (upper - lower)
+            }
+            OrbitAverage::Curvature => {
+                ((z_next - z) / (z - self.z_prev)).arg().abs()
+                    * SimdDouble::splat(std::f64::consts::FRAC_1_PI)
+            }
+        };
+        let added = active & statistic.is_finite();
+        let zero = SimdDouble::splat(0.0);
+        self.sum += added.select(statistic, zero);
+        self.terms += added.select(SimdDouble::splat(1.0), zero);
+        self.last = added.select(statistic, self.last);
+        self.z_prev = SimdComplex::select(active, z, self.z_prev);
+    }
+
+    /// Interpolates the average of each lane by `fraction`, see `smooth_average`.
+    #[inline(always)]
+    fn finish(&self, counts: Array<u8, N>, fraction: impl Fn(usize) -> f64) -> Array<u8, N> {
+        std::array::from_fn(|i| {
+            smooth_average(
+                counts[i],
+                self.sum[i],
+                self.last[i],
+                self.terms[i],
+                fraction(i),
+            )
+        })
+    }
+}
+
+/// Interpolates between the averages without and with the last statistic by `fraction`,
+/// which removes the banding between iteration counts, and maps the result to a palette value.
+/// Orbits that never stopped get `MAX_ITERS`, like with the escape-time rules.
+fn smooth_average(count: u8, sum: f64, last: f64, terms: f64, fraction: f64) -> u8 {
+    if count == MAX_ITERS || terms == 0.0 {
+        return if count == MAX_ITERS { MAX_ITERS } else { 0 };
+    }
+    let average = sum / terms;
+    let previous = if terms > 1.0 {
+        (sum - last) / (terms - 1.0)
+    } else {
+        average
+    };
+    let fraction = if fraction.is_nan() {
+        1.0
+    } else {
+        fraction.clamp(0.0, 1.0)
+    };
+    let value = previous + fraction * (average - previous);
+    (value.clamp(0.0, 1.0) * (MAX_ITERS - 1) as f64).round() as u8
+}
+
+/// How far past the bailout radius an escaped orbit got, from 1 just past the radius
+/// to 0 at the radius squared, where the orbit would have escaped one iteration sooner.
+fn escape_fraction(radius: f64, z_norm: f64) -> f64 {
+    1.0 + (radius.ln() / z_norm.ln()).log2()
+}
+
+/// How far below the tolerance the last step of a converged orbit was, from 1 just below
+/// the tolerance to 0 where the orbit would have converged one iteration sooner.
+fn convergence_fraction(tolerance: f64, previous_step: f64, last_step: f64) -> f64 {
+    (previous_step / tolerance).ln() / (previous_step / last_step).ln()
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| z * z + c)
+}
 
 #[allow(dead_code)]
-pub fn julia(mut z: SimdComplex, c: SimdComplex) -> Array<u8> {
+#[inline(always)]
+pub fn mandelbrot<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    julia(SimdComplex::default(), c, bailout)
+}
+
+/// Multibrot: `z = z^d + c` for any complex exponent `d`.
+/// Non-negative integer exponents use repeated squaring, while other exponents
+/// go through the complex logarithm, which is much slower.
+/// Julia sets of exponents with a non-positive real part are undefined at `z = 0`.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn multibrot_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    exponent: Complex,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    if exponent.im != 0.0 {
+        let exponent = SimdComplex::from_complex(exponent);
+        escape_time(z, bailout, |z| z.powc(exponent) + c)
+    } else if exponent.re >= 0.0 && exponent.re <= u32::MAX as f64 && exponent.re.fract() == 0.0 {
+        let exponent = exponent.re as u32;
+        escape_time(z, bailout, |z| z.powi(exponent) + c)
+    } else {
+        escape_time(z, bailout, |z| z.powf(exponent.re) + c)
+    }
+}
+
+/// Multibrot sets start from `z = 0`, or from `z = c` for exponents with a non-positive
+/// real part (except 0), where `0^d` is undefined.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn multibrot<const N: usize>(
+    c: SimdComplex<N>,
+    exponent: Complex,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    if multibrot_starts_from_c(exponent) {
+        multibrot_julia(c, c, exponent, bailout)
+    } else {
+        multibrot_julia(SimdComplex::default(), c, exponent, bailout)
+    }
+}
+
+fn multibrot_starts_from_c(exponent: Complex) -> bool {
+    exponent.re <= 0.0 && exponent != Complex::default()
+}
+
+/// Counts the iterations of `step` on `z` and its derivative `dz` until `z` escapes,
+/// and returns their values at that point.
+#[inline(always)]
+fn escape_time_with_derivative<const N: usize>(
+    mut z: SimdComplex<N>,
+    mut dz: SimdComplex<N>,
+    step: impl Fn(SimdComplex<N>, SimdComplex<N>) -> (SimdComplex<N>, SimdComplex<N>),
+) -> (Array<u8, N>, SimdComplex<N>, SimdComplex<N>) {
+    let escape_radius_squared = SimdDouble::splat(DERIVATIVE_ESCAPE_RADIUS_SQUARED);
     let mut cnt = SimdCounter::new();
     for _ in 0..MAX_ITERS {
-        cnt.increment_where(z.norm_squared().simd_lt(ESCAPE_RADIUS_SQUARED));
+        cnt.increment_where(z.norm_squared().simd_lt(escape_radius_squared));
         if !cnt.modified() {
             break;
         }
-        z = z * z + c;
+        let (z_next, dz_next) = step(z, dz);
+        z = SimdComplex::select(cnt.active(), z_next, z);
+        dz = SimdComplex::select(cnt.active(), dz_next, dz);
+    }
+    (cnt.counts(), z, dz)
+}
+
+/// Returns the exterior distance estimate `|z| ln|z| / |dz|` of each lane,
+/// or 0 for lanes that did not escape.
+#[inline(always)]
+fn distance_estimate<const N: usize>(z: SimdComplex<N>, dz: SimdComplex<N>) -> SimdDouble<N> {
+    let norm_squared = z.norm_squared();
+    let modulus = norm_squared.sqrt();
+    let distance = modulus * modulus.ln() / dz.norm_squared().sqrt();
+    let escaped = norm_squared.simd_ge(SimdDouble::splat(DERIVATIVE_ESCAPE_RADIUS_SQUARED));
+    escaped.select(distance, SimdDouble::splat(0.0))
+}
+
+/// Same as `julia`, but also returns the final `z` and its derivative `dz/dz0` of each lane.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn julia_with_derivative<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>, SimdComplex<N>) {
+    escape_time_with_derivative(z, SimdComplex::splat(1.0, 0.0), |z, dz| {
+        (z * z + c, z * dz * 2.0)
+    })
+}
+
+/// Same as `mandelbrot`, but also returns the final `z` and its derivative `dz/dc` of each lane.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn mandelbrot_with_derivative<const N: usize>(
+    c: SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>, SimdComplex<N>) {
+    escape_time_with_derivative(SimdComplex::default(), SimdComplex::default(), |z, dz| {
+        (z * z + c, z * dz * 2.0 + 1.0)
+    })
+}
+
+/// Same as `julia`, but also returns the distance estimate of each lane.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn julia_with_distance<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+) -> (Array<u8, N>, SimdDouble<N>) {
+    let (counts, z, dz) = julia_with_derivative(z, c);
+    (counts, distance_estimate(z, dz))
+}
+
+/// Same as `mandelbrot`, but also returns the distance estimate of each lane.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn mandelbrot_with_distance<const N: usize>(
+    c: SimdComplex<N>,
+) -> (Array<u8, N>, SimdDouble<N>) {
+    let (counts, z, dz) = mandelbrot_with_derivative(c);
+    (counts, distance_estimate(z, dz))
+}
+
+/// Iterates `step` on `z` until it escapes, and returns the smallest distance between
+/// the orbit and `trap`, along with the offset from the trap center where it was reached.
+#[inline(always)]
+fn trap_distance<const N: usize>(
+    mut z: SimdComplex<N>,
+    bailout: Bailout,
+    trap: OrbitTrap,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> (SimdDouble<N>, SimdComplex<N>) {
+    let center = SimdComplex::from_complex(trap.center);
+    let mut min_distance = SimdDouble::splat(f64::INFINITY);
+    let mut closest = SimdComplex::default();
+    let mut cnt = SimdCounter::new();
+    for _ in 0..MAX_ITERS {
+        cnt.increment_where(bailout.is_bounded(z));
+        if !cnt.modified() {
+            break;
+        }
+        z = SimdComplex::select(cnt.active(), step(z), z);
+        let distance = trap.distance(z);
+        let closer = cnt.active() & distance.simd_lt(min_distance);
+        min_distance = closer.select(distance, min_distance);
+        closest = SimdComplex::select(closer, z - center, closest);
+    }
+    (min_distance, closest)
+}
+
+/// Same as `julia`, but returns the orbit-trap distance of each lane, see `OrbitTrap`.
+#[inline(always)]
+pub fn julia_with_trap<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+    trap: OrbitTrap,
+) -> (SimdDouble<N>, SimdComplex<N>) {
+    trap_distance(z, bailout, trap, |z| z * z + c)
+}
+
+/// Same as `mandelbrot`, but returns the orbit-trap distance of each lane, see `OrbitTrap`.
+#[inline(always)]
+pub fn mandelbrot_with_trap<const N: usize>(
+    c: SimdComplex<N>,
+    bailout: Bailout,
+    trap: OrbitTrap,
+) -> (SimdDouble<N>, SimdComplex<N>) {
+    trap_distance(SimdComplex::default(), bailout, trap, |z| z * z + c)
+}
+
+/// Same as `julia`, but averages a statistic over the orbit instead of counting iterations.
+#[inline(always)]
+pub fn julia_with_average<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+    average: OrbitAverage,
+) -> Array<u8, N> {
+    let mut accumulator = AverageAccumulator::new(average, c);
+    let (counts, z) = escape_time_while_with_hook(
+        z,
+        |z| bailout.is_bounded(z),
+        |z| z * z + c,
+        |active, z, z_next| accumulator.add_step(active, z, z_next),
+    );
+    let z_norm = z.norm_squared().sqrt();
+    accumulator.finish(counts, |i| escape_fraction(bailout.radius, z_norm[i]))
+}
+
+/// Same as `mandelbrot`, but averages a statistic over the orbit instead of counting iterations.
+#[inline(always)]
+pub fn mandelbrot_with_average<const N: usize>(
+    c: SimdComplex<N>,
+    bailout: Bailout,
+    average: OrbitAverage,
+) -> Array<u8, N> {
+    julia_with_average(SimdComplex::default(), c, bailout, average)
+}
+
+/// Burning Ship: `z = (|Re z| + i|Im z|)^2 + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn burning_ship_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| {
+        let z = z.abs_parts();
+        z * z + c
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn burning_ship<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    burning_ship_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Tricorn (Mandelbar): `z = conj(z)^2 + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn tricorn_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| {
+        let z = z.conj();
+        z * z + c
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn tricorn<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    tricorn_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Celtic: `z = |Re z^2| + i Im z^2 + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn celtic_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| (z * z).abs_re() + c)
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn celtic<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    celtic_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Perpendicular Mandelbrot: `z = (|Re z| - i Im z)^2 + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn perpendicular_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| {
+        let z = z.abs_re().conj();
+        z * z + c
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn perpendicular_mandelbrot<const N: usize>(
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    perpendicular_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Buffalo: `z = |Re z^2| - i|Im z^2| + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn buffalo_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| (z * z).abs_parts().conj() + c)
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn buffalo<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    buffalo_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Heart: `z = (|Re z| + i Im z)^2 + c`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn heart_julia<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time(z, bailout, |z| {
+        let z = z.abs_re();
+        z * z + c
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn heart<const N: usize>(c: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    heart_julia(SimdComplex::default(), c, bailout)
+}
+
+/// Phoenix: `z = z^2 + c + p * z_prev`, where `z_prev` is the previous iterate (initially 0).
+#[allow(dead_code)]
+#[inline(always)]
+pub fn phoenix_julia<const N: usize>(
+    mut z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    p: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    let mut z_prev = SimdComplex::default();
+    let mut cnt = SimdCounter::new();
+    for _ in 0..MAX_ITERS {
+        cnt.increment_where(bailout.is_bounded(z));
+        if !cnt.modified() {
+            break;
+        }
+        (z, z_prev) = (z * z + c + p * z_prev, z);
     }
     cnt.counts()
 }
 
 #[allow(dead_code)]
-pub fn mandelbrot(c: SimdComplex) -> Array<u8> {
-    julia(SimdComplex::default(), c)
+#[inline(always)]
+pub fn phoenix<const N: usize>(
+    c: SimdComplex<N>,
+    p: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    phoenix_julia(SimdComplex::default(), c, p, bailout)
+}
+
+/// Counts the iterations of a magnet map until `z` escapes or converges to 1.
+#[inline(always)]
+fn magnet_time<const N: usize>(
+    z: SimdComplex<N>,
+    step: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    let escape_radius_squared = SimdDouble::splat(MAGNET_ESCAPE_RADIUS_SQUARED);
+    let convergence_radius_squared = SimdDouble::splat(MAGNET_CONVERGENCE_RADIUS_SQUARED);
+    escape_time_while(
+        z,
+        |z| {
+            z.norm_squared().simd_lt(escape_radius_squared)
+                & (z - 1.0).norm_squared().simd_ge(convergence_radius_squared)
+        },
+        step,
+    )
 }
 
+/// Magnet type I: `z = ((z^2 + c - 1) / (2z + c - 2))^2`
 #[allow(dead_code)]
-pub fn nova(
-    mut z: SimdComplex,
-    c: SimdComplex,
-    f: impl Fn(SimdComplex) -> SimdComplex,
-    df: impl Fn(SimdComplex) -> SimdComplex,
-) -> Array<u8> {
+#[inline(always)]
+pub fn magnet1_julia<const N: usize>(z: SimdComplex<N>, c: SimdComplex<N>) -> Array<u8, N> {
+    magnet_time(z, |z| {
+        let q = (z * z + c - 1.0) / (z * 2.0 + c - 2.0);
+        q * q
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn magnet1<const N: usize>(c: SimdComplex<N>) -> Array<u8, N> {
+    magnet1_julia(SimdComplex::default(), c)
+}
+
+/// Magnet type II:
+/// `z = ((z^3 + 3(c - 1)z + (c - 1)(c - 2)) / (3z^2 + 3(c - 2)z + (c - 1)(c - 2) + 1))^2`
+#[allow(dead_code)]
+#[inline(always)]
+pub fn magnet2_julia<const N: usize>(z: SimdComplex<N>, c: SimdComplex<N>) -> Array<u8, N> {
+    let c1 = c - 1.0;
+    let c2 = c - 2.0;
+    let c1c2 = c1 * c2;
+    magnet_time(z, |z| {
+        let q = (z * z * z + c1 * z * 3.0 + c1c2) / (z * z * 3.0 + c2 * z * 3.0 + c1c2 + 1.0);
+        q * q
+    })
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn magnet2<const N: usize>(c: SimdComplex<N>) -> Array<u8, N> {
+    magnet2_julia(SimdComplex::default(), c)
+}
+
+/// Lambda (logistic map): `z = λz(1 - z)`.
+/// It is conjugate to `w^2 + λ/2 - λ^2/4` through `w = λ(1/2 - z)`, so an orbit escapes
+/// exactly when `λ(z - 1/2)` escapes.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn lambda_julia<const N: usize>(
+    z: SimdComplex<N>,
+    lambda: SimdComplex<N>,
+    bailout: Bailout,
+) -> Array<u8, N> {
+    escape_time_while(
+        z,
+        |z| bailout.is_bounded(lambda * (z - 0.5)),
+        |z| lambda * z * (SimdComplex::splat(1.0, 0.0) - z),
+    )
+}
+
+/// Starts from the critical point `z = 1/2`.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn lambda<const N: usize>(lambda: SimdComplex<N>, bailout: Bailout) -> Array<u8, N> {
+    lambda_julia(SimdComplex::splat(0.5, 0.0), lambda, bailout)
+}
+
+/// Iterates a formula parsed at runtime. `c` starts at `pixel` unless the formula says otherwise.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn formula<const N: usize>(
+    formula: &Formula,
+    pixel: SimdComplex<N>,
+    seed: SimdComplex<N>,
+) -> Array<u8, N> {
+    let mut vars = formula.initial_variables(pixel, seed);
     let mut cnt = SimdCounter::new();
     for _ in 0..MAX_ITERS {
-        let z_next = z - f(z) / df(z) + c;
-        cnt.increment_where((z_next - z).norm_squared().simd_ge(EPSILON));
+        cnt.increment_where(!formula.bailout(&vars));
         if !cnt.modified() {
             break;
         }
-        z = z_next;
+        formula.step(&mut vars);
     }
     cnt.counts()
 }
 
+/// Nova fractal: `z - a * f(z) / f'(z) + c`, with the relaxation factor `a` from the params.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn nova<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    df: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    nova_with_root(z, c, params, f, df).0
+}
+
+/// Same as `nova`, but also returns the value each lane converged to.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn nova_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    df: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let a = SimdComplex::from_complex(params.relaxation);
+    convergence_time(z, params, |z| z - a * (f(z) / df(z)) + c)
+}
+
+/// Same as `nova`, but averages a statistic over the orbit instead of counting iterations.
+#[inline(always)]
+pub fn nova_with_average<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    df: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    average: OrbitAverage,
+) -> Array<u8, N> {
+    let a = SimdComplex::from_complex(params.relaxation);
+    let step = |z| z - a * (f(z) / df(z)) + c;
+    let mut accumulator = AverageAccumulator::new(average, c);
+    // The last value that was averaged, which is one step before the converged value
+    let mut z_last = z;
+    let (counts, _) = convergence_time_with_hook(z, params, step, |active, z, z_next| {
+        accumulator.add_step(active, z, z_next);
+        z_last = SimdComplex::select(active, z_next, z_last);
+    });
+    let previous_step = (z_last - accumulator.z_prev).norm_squared().sqrt();
+    let last_step = (step(z_last) - z_last).norm_squared().sqrt();
+    accumulator.finish(counts, |i| {
+        convergence_fraction(params.tolerance, previous_step[i], last_step[i])
+    })
+}
+
+/// The classic Newton's method, `nova` without relaxation or `c`, with the default params.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn newton<const N: usize>(
+    z: SimdComplex<N>,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    df: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    newton_with_root(z, RootFindingParams::DEFAULT, f, df).0
+}
+
+/// Same as `nova_with_root` with `c = 0`, so that lanes converge to the roots of `f`.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn newton_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+    df: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    nova_with_root(z, SimdComplex::default(), params, f, df)
+}
+
+/// Same as `nova`, with the derivative of `f` computed by automatic differentiation.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn nova_auto<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Dual<N>) -> Dual<N>,
+) -> Array<u8, N> {
+    nova_auto_with_root(z, c, params, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn nova_auto_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Dual<N>) -> Dual<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let a = SimdComplex::from_complex(params.relaxation);
+    convergence_time(z, params, |z| {
+        let fz = f(Dual::variable(z));
+        z - a * (fz.value() / fz.derivative(1)) + c
+    })
+}
+
+/// Same as `newton`, with the derivative of `f` computed by automatic differentiation.
 #[allow(dead_code)]
-pub fn newton(
-    z: SimdComplex,
-    f: impl Fn(SimdComplex) -> SimdComplex,
-    df: impl Fn(SimdComplex) -> SimdComplex,
-) -> Array<u8> {
-    nova(z, SimdComplex::default(), f, df)
+#[inline(always)]
+pub fn newton_auto<const N: usize>(
+    z: SimdComplex<N>,
+    f: impl Fn(Dual<N>) -> Dual<N>,
+) -> Array<u8, N> {
+    newton_auto_with_root(z, RootFindingParams::DEFAULT, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn newton_auto_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Dual<N>) -> Dual<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    nova_auto_with_root(z, SimdComplex::default(), params, f)
+}
+
+/// Halley's method: `z - a * 2ff' / (2f'^2 - ff'') + c`. It converges cubically to simple roots.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn halley<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, 3>) -> Jet<N, 3>,
+) -> Array<u8, N> {
+    halley_with_root(z, c, params, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn halley_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, 3>) -> Jet<N, 3>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let a = SimdComplex::from_complex(params.relaxation);
+    convergence_time(z, params, |z| {
+        let [fz, dfz, d2fz] = f(Jet::variable(z)).derivatives();
+        let fz2 = fz * 2.0;
+        z - a * (fz2 * dfz) / (dfz * dfz * 2.0 - fz * d2fz) + c
+    })
+}
+
+/// Householder's method of order `TERMS - 1`: `z + a * d (1/f)^(d-1) / (1/f)^(d) + c`.
+/// Order 1 is Newton's method and order 2 is Halley's method.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn householder<const N: usize, const TERMS: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, TERMS>) -> Jet<N, TERMS>,
+) -> Array<u8, N> {
+    householder_with_root(z, c, params, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn householder_with_root<const N: usize, const TERMS: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, TERMS>) -> Jet<N, TERMS>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    const {
+        assert!(
+            TERMS >= 2,
+            "Householder's method needs at least one derivative"
+        )
+    };
+    let a = SimdComplex::from_complex(params.relaxation);
+    convergence_time(z, params, |z| {
+        // With g = 1/f, d * g^(d-1) / g^(d) is the ratio of consecutive Taylor coefficients
+        let g = f(Jet::variable(z)).recip();
+        z + a * (g.coefficient(TERMS - 2) / g.coefficient(TERMS - 1)) + c
+    })
+}
+
+/// Schröder's method: `z - a * ff' / (f'^2 - ff'') + c`. Unlike Newton's method,
+/// it converges quadratically to multiple roots as well.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn schroder<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, 3>) -> Jet<N, 3>,
+) -> Array<u8, N> {
+    schroder_with_root(z, c, params, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn schroder_with_root<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(Jet<N, 3>) -> Jet<N, 3>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let a = SimdComplex::from_complex(params.relaxation);
+    convergence_time(z, params, |z| {
+        let [fz, dfz, d2fz] = f(Jet::variable(z)).derivatives();
+        z - a * (fz * dfz) / (dfz * dfz - fz * d2fz) + c
+    })
+}
+
+/// Secant method: `z - a * f(z) (z - z_prev) / (f(z) - f(z_prev)) + c`.
+/// It needs no derivative, and starts from `z` and a point next to it.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn secant<const N: usize>(
+    z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> Array<u8, N> {
+    secant_with_root(z, c, params, f).0
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn secant_with_root<const N: usize>(
+    mut z: SimdComplex<N>,
+    c: SimdComplex<N>,
+    params: RootFindingParams,
+    f: impl Fn(SimdComplex<N>) -> SimdComplex<N>,
+) -> (Array<u8, N>, SimdComplex<N>) {
+    let a = SimdComplex::from_complex(params.relaxation);
+    let mut cnt = SimdCounter::new();
+    let mut z_prev = z + SECANT_INITIAL_OFFSET;
+    let mut f_prev = f(z_prev);
+    let mut fz = f(z);
+    for _ in 0..MAX_ITERS {
+        let z_next = z - a * fz * (z - z_prev) / (fz - f_prev) + c;
+        cnt.increment_where(params.keeps_iterating(z, z_next));
+        if !cnt.modified() {
+            break;
+        }
+        let active = cnt.active();
+        z_prev = SimdComplex::select(active, z, z_prev);
+        f_prev = SimdComplex::select(active, fz, f_prev);
+        z = SimdComplex::select(active, z_next, z);
+        fz = f(z);
+    }
+    (cnt.counts(), z)
+}
+
+/// Plain `Complex`-based versions of the rules above.
+/// They are slow, but serve as a reference for verifying the SIMD implementations.
+#[allow(dead_code)]
+pub mod scalar {
+    use super::{
+        convergence_fraction, escape_fraction, multibrot_starts_from_c, smooth_average, Bailout,
+        BailoutTest, OrbitAverage, OrbitTrap, RootFindingParams, TrapShape,
+        DERIVATIVE_ESCAPE_RADIUS_SQUARED, MAGNET_CONVERGENCE_RADIUS_SQUARED,
+        MAGNET_ESCAPE_RADIUS_SQUARED, MAX_ITERS, SECANT_INITIAL_OFFSET,
+    };
+    use crate::utils::Complex;
+    use nalgebra::ComplexField;
+
+    fn is_bounded(bailout: Bailout, z: Complex) -> bool {
+        let radius = bailout.radius;
+        match bailout.test {
+            BailoutTest::Norm => z.norm_sqr() < radius * radius,
+            BailoutTest::Real => z.re.abs() < radius,
+            BailoutTest::Imaginary => z.im.abs() < radius,
+            BailoutTest::Manhattan => z.re.abs() + z.im.abs() < radius,
+            BailoutTest::Min => z.re.abs().min(z.im.abs()) < radius,
+            BailoutTest::Max => z.re.abs().max(z.im.abs()) < radius,
+        }
+    }
+
+    fn escape_time(z: Complex, bailout: Bailout, step: impl Fn(Complex) -> Complex) -> u8 {
+        escape_time_while(z, |z| is_bounded(bailout, z), step)
+    }
+
+    fn escape_time_while(
+        z: Complex,
+        keep_iterating: impl Fn(Complex) -> bool,
+        step: impl Fn(Complex) -> Complex,
+    ) -> u8 {
+        escape_time_while_with_hook(z, keep_iterating, step, |_, _| ()).0
+    }
+
+    fn escape_time_while_with_hook(
+        mut z: Complex,
+        keep_iterating: impl Fn(Complex) -> bool,
+        step: impl Fn(Complex) -> Complex,
+        mut hook: impl FnMut(Complex, Complex),
+    ) -> (u8, Complex) {
+        let mut count = 0;
+        while count < MAX_ITERS && keep_iterating(z) {
+            count += 1;
+            let z_next = step(z);
+            hook(z, z_next);
+            z = z_next;
+        }
+        (count, z)
+    }
+
+    fn abs_parts(z: Complex) -> Complex {
+        Complex::new(z.re.abs(), z.im.abs())
+    }
+
+    fn abs_re(z: Complex) -> Complex {
+        Complex::new(z.re.abs(), z.im)
+    }
+
+    pub fn julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| z * z + c)
+    }
+
+    pub fn mandelbrot(c: Complex, bailout: Bailout) -> u8 {
+        julia(Complex::default(), c, bailout)
+    }
+
+    /// Same repeated squaring as `SimdComplex::powi`, so that results match exactly.
+    fn powi(z: Complex, exp: u32) -> Complex {
+        if exp == 0 {
+            return Complex::new(1.0, 0.0);
+        }
+        let mut base = z;
+        let mut exp = exp;
+        while exp & 1 == 0 {
+            base = base * base;
+            exp >>= 1;
+        }
+        let mut res = base;
+        exp >>= 1;
+        while exp > 0 {
+            base = base * base;
+            if exp & 1 == 1 {
+                res *= base;
+            }
+            exp >>= 1;
+        }
+        res
+    }
+
+    pub fn multibrot_julia(z: Complex, c: Complex, exponent: Complex, bailout: Bailout) -> u8 {
+        if exponent.im != 0.0 {
+            escape_time(z, bailout, |z| powc(z, exponent) + c)
+        } else if exponent.re >= 0.0 && exponent.re <= u32::MAX as f64 && exponent.re.fract() == 0.0
+        {
+            escape_time(z, bailout, |z| powi(z, exponent.re as u32) + c)
+        } else {
+            escape_time(z, bailout, |z| z.powf(exponent.re) + c)
+        }
+    }
+
+    pub fn multibrot(c: Complex, exponent: Complex, bailout: Bailout) -> u8 {
+        if multibrot_starts_from_c(exponent) {
+            multibrot_julia(c, c, exponent, bailout)
+        } else {
+            multibrot_julia(Complex::default(), c, exponent, bailout)
+        }
+    }
+
+    /// Same special case of zero as `SimdComplex::powc`.
+    fn powc(z: Complex, exp: Complex) -> Complex {
+        if z == Complex::default() && exp.re > 0.0 {
+            Complex::default()
+        } else {
+            z.powc(exp)
+        }
+    }
+
+    fn escape_time_with_derivative(
+        mut z: Complex,
+        mut dz: Complex,
+        step: impl Fn(Complex, Complex) -> (Complex, Complex),
+    ) -> (u8, Complex, Complex) {
+        let mut count = 0;
+        while count < MAX_ITERS && z.norm_sqr() < DERIVATIVE_ESCAPE_RADIUS_SQUARED {
+            count += 1;
+            (z, dz) = step(z, dz);
+        }
+        (count, z, dz)
+    }
+
+    fn distance_estimate(z: Complex, dz: Complex) -> f64 {
+        if z.norm_sqr() < DERIVATIVE_ESCAPE_RADIUS_SQUARED {
+            return 0.0;
+        }
+        let modulus = z.norm_sqr().sqrt();
+        modulus * modulus.ln() / dz.norm_sqr().sqrt()
+    }
+
+    pub fn julia_with_derivative(z: Complex, c: Complex) -> (u8, Complex, Complex) {
+        escape_time_with_derivative(z, Complex::new(1.0, 0.0), |z, dz| (z * z + c, z * dz * 2.0))
+    }
+
+    pub fn mandelbrot_with_derivative(c: Complex) -> (u8, Complex, Complex) {
+        escape_time_with_derivative(Complex::default(), Complex::default(), |z, dz| {
+            (z * z + c, z * dz * 2.0 + 1.0)
+        })
+    }
+
+    pub fn julia_with_distance(z: Complex, c: Complex) -> (u8, f64) {
+        let (count, z, dz) = julia_with_derivative(z, c);
+        (count, distance_estimate(z, dz))
+    }
+
+    pub fn mandelbrot_with_distance(c: Complex) -> (u8, f64) {
+        let (count, z, dz) = mandelbrot_with_derivative(c);
+        (count, distance_estimate(z, dz))
+    }
+
+    fn distance_to_trap(trap: OrbitTrap, z: Complex) -> f64 {
+        let z = z - trap.center;
+        match trap.shape {
+            TrapShape::Point => z.norm_sqr().sqrt(),
+            TrapShape::Line => z.im.abs(),
+            TrapShape::Cross => z.re.abs().min(z.im.abs()),
+            TrapShape::Circle => (z.norm_sqr().sqrt() - trap.size).abs(),
+            TrapShape::Image => (z.re.abs().max(z.im.abs()) - trap.size * 0.5).max(0.0),
+        }
+    }
+
+    fn trap_distance(
+        mut z: Complex,
+        bailout: Bailout,
+        trap: OrbitTrap,
+        step: impl Fn(Complex) -> Complex,
+    ) -> (f64, Complex) {
+        let mut min_distance = f64::INFINITY;
+        let mut closest = Complex::default();
+        let mut count = 0;
+        while count < MAX_ITERS && is_bounded(bailout, z) {
+            count += 1;
+            z = step(z);
+            let distance = distance_to_trap(trap, z);
+            if distance < min_distance {
+                min_distance = distance;
+                closest = z - trap.center;
+            }
+        }
+        (min_distance, closest)
+    }
+
+    pub fn julia_with_trap(
+        z: Complex,
+        c: Complex,
+        bailout: Bailout,
+        trap: OrbitTrap,
+    ) -> (f64, Complex) {
+        trap_distance(z, bailout, trap, |z| z * z + c)
+    }
+
+    pub fn mandelbrot_with_trap(c: Complex, bailout: Bailout, trap: OrbitTrap) -> (f64, Complex) {
+        trap_distance(Complex::default(), bailout, trap, |z| z * z + c)
+    }
+
+    pub fn julia_with_average(
+        z: Complex,
+        c: Complex,
+        bailout: Bailout,
+        average: OrbitAverage,
+    ) -> u8 {
+        let mut accumulator = AverageAccumulator::new(average, c);
+        let (count, z) = escape_time_while_with_hook(
+            z,
+            |z| is_bounded(bailout, z),
+            |z| z * z + c,
+            |z, z_next| accumulator.add_step(z, z_next),
+        );
+        accumulator.finish(count, escape_fraction(bailout.radius, z.norm_sqr().sqrt()))
+    }
+
+    pub fn mandelbrot_with_average(c: Complex, bailout: Bailout, average: OrbitAverage) -> u8 {
+        julia_with_average(Complex::default(), c, bailout, average)
+    }
+
+    pub fn burning_ship_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| abs_parts(z) * abs_parts(z) + c)
+    }
+
+    pub fn burning_ship(c: Complex, bailout: Bailout) -> u8 {
+        burning_ship_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn tricorn_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| z.conj() * z.conj() + c)
+    }
+
+    pub fn tricorn(c: Complex, bailout: Bailout) -> u8 {
+        tricorn_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn celtic_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| abs_re(z * z) + c)
+    }
+
+    pub fn celtic(c: Complex, bailout: Bailout) -> u8 {
+        celtic_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn perpendicular_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| abs_re(z).conj() * abs_re(z).conj() + c)
+    }
+
+    pub fn perpendicular_mandelbrot(c: Complex, bailout: Bailout) -> u8 {
+        perpendicular_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn buffalo_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| abs_parts(z * z).conj() + c)
+    }
+
+    pub fn buffalo(c: Complex, bailout: Bailout) -> u8 {
+        buffalo_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn heart_julia(z: Complex, c: Complex, bailout: Bailout) -> u8 {
+        escape_time(z, bailout, |z| abs_re(z) * abs_re(z) + c)
+    }
+
+    pub fn heart(c: Complex, bailout: Bailout) -> u8 {
+        heart_julia(Complex::default(), c, bailout)
+    }
+
+    pub fn phoenix_julia(mut z: Complex, c: Complex, p: Complex, bailout: Bailout) -> u8 {
+        let mut z_prev = Complex::default();
+        let mut count = 0;
+        while count < MAX_ITERS && is_bounded(bailout, z) {
+            count += 1;
+            (z, z_prev) = (z * z + c + p * z_prev, z);
+        }
+        count
+    }
+
+    pub fn phoenix(c: Complex, p: Complex, bailout: Bailout) -> u8 {
+        phoenix_julia(Complex::default(), c, p, bailout)
+    }
+
+    fn magnet_time(z: Complex, step: impl Fn(Complex) -> Complex) -> u8 {
+        escape_time_while(
+            z,
+            |z| {
+                z.norm_sqr() < MAGNET_ESCAPE_RADIUS_SQUARED
+                    && (z - 1.0).norm_sqr() >= MAGNET_CONVERGENCE_RADIUS_SQUARED
+            },
+            step,
+        )
+    }
+
+    pub fn magnet1_julia(z: Complex, c: Complex) -> u8 {
+        magnet_time(z, |z| {
+            let q = (z * z + c - 1.0) / (z * 2.0 + c - 2.0);
+            q * q
+        })
+    }
+
+    pub fn magnet1(c: Complex) -> u8 {
+        magnet1_julia(Complex::default(), c)
+    }
+
+    pub fn magnet2_julia(z: Complex, c: Complex) -> u8 {
+        let c1 = c - 1.0;
+        let c2 = c - 2.0;
+        magnet_time(z, |z| {
+            let q =
+                (z * z * z + c1 * z * 3.0 + c1 * c2) / (z * z * 3.0 + c2 * z * 3.0 + c1 * c2 + 1.0);
+            q * q
+        })
+    }
+
+    pub fn magnet2(c: Complex) -> u8 {
+        magnet2_julia(Complex::default(), c)
+    }
+
+    pub fn lambda_julia(z: Complex, lambda: Complex, bailout: Bailout) -> u8 {
+        escape_time_while(
+            z,
+            |z| is_bounded(bailout, lambda * (z - 0.5)),
+            |z| lambda * z * (1.0 - z),
+        )
+    }
+
+    pub fn lambda(lambda: Complex, bailout: Bailout) -> u8 {
+        lambda_julia(Complex::new(0.5, 0.0), lambda, bailout)
+    }
+
+    fn keeps_iterating(params: RootFindingParams, z: Complex, z_next: Complex) -> bool {
+        let moved = (z_next - z).norm_sqr() >= params.tolerance * params.tolerance;
+        moved && is_within_bailout(params, z_next)
+    }
+
+    fn is_within_bailout(params: RootFindingParams, z: Complex) -> bool {
+        z.norm_sqr() <= params.bailout * params.bailout
+    }
+
+    fn convergence_time(
+        z: Complex,
+        params: RootFindingParams,
+        step: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        convergence_time_with_hook(z, params, step, |_, _| ())
+    }
+
+    fn convergence_time_with_hook(
+        mut z: Complex,
+        params: RootFindingParams,
+        step: impl Fn(Complex) -> Complex,
+        mut hook: impl FnMut(Complex, Complex),
+    ) -> (u8, Complex) {
+        let mut count = 0;
+        while count < MAX_ITERS {
+            let z_next = step(z);
+            if !keeps_iterating(params, z, z_next) {
+                if is_within_bailout(params, z_next) {
+                    z = z_next;
+                }
+                break;
+            }
+            count += 1;
+            hook(z, z_next);
+            z = z_next;
+        }
+        (count, z)
+    }
+
+    struct AverageAccumulator {
+        average: OrbitAverage,
+        c: Complex,
+        z_prev: Complex,
+        sum: f64,
+        last: f64,
+        terms: f64,
+    }
+
+    impl AverageAccumulator {
+        fn new(average: OrbitAverage, c: Complex) -> Self {
+            Self {
+                average,
+                c,
+                z_prev: Complex::new(f64::NAN, f64::NAN),
+                sum: 0.0,
+                last: 0.0,
+                terms: 0.0,
+            }
+        }
+
+        fn add_step(&mut self, z: Complex, z_next: Complex) {
+            let statistic = match self.average {
+                OrbitAverage::Stripe { density } => {
+                    (z_next.im.atan2(z_next.re) * density).sin() * 0.5 + 0.5
+                }
+                OrbitAverage::TriangleInequality => {
+                    let z_norm_squared = z.norm_sqr();
+                    let c_norm = self.c.norm_sqr().sqrt();
+                    let lower = (z_norm_squared - c_norm).abs();
+                    let upper = z_norm_squared + c_norm;
+                    (z_next.norm_sqr().sqrt() - lower) / (upper - lower)
+                }
+                OrbitAverage::Curvature => {
+                    let turn = (z_next - z) / (z - self.z_prev);
+                    turn.im.atan2(turn.re).abs() * std::f64::consts::FRAC_1_PI
+                }
+            };
+            if statistic.is_finite() {
+                self.sum += statistic;
+                self.terms += 1.0;
+                self.last = statistic;
+            }
+            self.z_prev = z;
+        }
+
+        fn finish(&self, count: u8, fraction: f64) -> u8 {
+            smooth_average(count, self.sum, self.last, self.terms, fraction)
+        }
+    }
+
+    pub fn nova(
+        z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+    ) -> u8 {
+        nova_with_root(z, c, params, f, df).0
+    }
+
+    pub fn nova_with_root(
+        z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        let a = params.relaxation;
+        convergence_time(z, params, |z| z - a * (f(z) / df(z)) + c)
+    }
+
+    pub fn nova_with_average(
+        z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+        average: OrbitAverage,
+    ) -> u8 {
+        let a = params.relaxation;
+        let step = |z| z - a * (f(z) / df(z)) + c;
+        let mut accumulator = AverageAccumulator::new(average, c);
+        let mut z_last = z;
+        let (count, _) = convergence_time_with_hook(z, params, step, |z, z_next| {
+            accumulator.add_step(z, z_next);
+            z_last = z_next;
+        });
+        let previous_step = (z_last - accumulator.z_prev).norm_sqr().sqrt();
+        let last_step = (step(z_last) - z_last).norm_sqr().sqrt();
+        accumulator.finish(
+            count,
+            convergence_fraction(params.tolerance, previous_step, last_step),
+        )
+    }
+
+    pub fn newton(
+        z: Complex,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+    ) -> u8 {
+        newton_with_root(z, RootFindingParams::DEFAULT, f, df).0
+    }
+
+    pub fn newton_with_root(
+        z: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        nova_with_root(z, Complex::default(), params, f, df)
+    }
+
+    pub fn halley(
+        z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+        d2f: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        let a = params.relaxation;
+        convergence_time(z, params, |z| {
+            let (fz, dfz, d2fz) = (f(z), df(z), d2f(z));
+            let fz2 = fz * 2.0;
+            z - a * (fz2 * dfz) / (dfz * dfz * 2.0 - fz * d2fz) + c
+        })
+    }
+
+    pub fn schroder(
+        z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+        df: impl Fn(Complex) -> Complex,
+        d2f: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        let a = params.relaxation;
+        convergence_time(z, params, |z| {
+            let (fz, dfz, d2fz) = (f(z), df(z), d2f(z));
+            z - a * (fz * dfz) / (dfz * dfz - fz * d2fz) + c
+        })
+    }
+
+    pub fn secant(
+        mut z: Complex,
+        c: Complex,
+        params: RootFindingParams,
+        f: impl Fn(Complex) -> Complex,
+    ) -> (u8, Complex) {
+        let a = params.relaxation;
+        let mut z_prev = z + SECANT_INITIAL_OFFSET;
+        let mut f_prev = f(z_prev);
+        let mut fz = f(z);
+        let mut count = 0;
+        while count < MAX_ITERS {
+            let z_next = z - a * fz * (z - z_prev) / (fz - f_prev) + c;
+            if !keeps_iterating(params, z, z_next) {
+                break;
+            }
+            count += 1;
+            z_prev = z;
+            f_prev = fz;
+            z = z_next;
+            fz = f(z);
+        }
+        (count, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        autodiff::Jet,
+        rules::{
+            self, convergence_fraction, escape_fraction, scalar, smooth_average, Bailout,
+            BailoutTest, OrbitAverage, OrbitTrap, RootFindingParams, TrapShape, MAX_ITERS,
+        },
+        simd::{Array, SimdComplex, SimdDouble},
+        utils::Complex,
+    };
+
+    const SAMPLES: usize = 4096;
+    const AVERAGES: [OrbitAverage; 3] = [
+        OrbitAverage::Stripe { density: 5.0 },
+        OrbitAverage::TriangleInequality,
+        OrbitAverage::Curvature,
+    ];
+    const SAMPLE_RADIUS: f64 = 3.0;
+
+    #[test]
+    fn julia_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::julia(z, c, bailout), |z, c| {
+            scalar::julia(z, c, bailout)
+        });
+    }
+
+    #[test]
+    fn mandelbrot_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|_z, c| rules::mandelbrot(c, bailout), |_, c| {
+            scalar::mandelbrot(c, bailout)
+        });
+    }
+
+    #[test]
+    fn multibrot_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        for exponent in [
+            Complex::new(0.0, 0.0),
+            Complex::new(3.0, 0.0),
+            Complex::new(6.0, 0.0),
+            Complex::new(2.5, 0.0),
+            Complex::new(-2.0, 0.0),
+            Complex::new(2.0, 0.5),
+        ] {
+            check_rule!(
+                |z, c| rules::multibrot_julia(z, c, exponent, bailout),
+                |z, c| scalar::multibrot_julia(z, c, exponent, bailout)
+            );
+            check_rule!(|_z, c| rules::multibrot(c, exponent, bailout), |_, c| {
+                scalar::multibrot(c, exponent, bailout)
+            });
+        }
+    }
+
+    #[test]
+    fn multibrot_interiors_are_found_for_any_exponent() {
+        let bailout = Bailout::DEFAULT;
+        let count = |c: Complex, exponent| {
+            rules::multibrot(SimdComplex::<4>::from_complex(c), exponent, bailout)
+        };
+        assert_eq!(
+            count(Complex::new(-0.1, 0.1), Complex::new(2.0, 0.1)),
+            [MAX_ITERS; 4]
+        );
+        assert_eq!(
+            count(Complex::new(-0.1, 0.1), Complex::new(2.5, 0.0)),
+            [MAX_ITERS; 4]
+        );
+        // Starts from c, then converges to the fixed point of z = z^-2 + 1.2
+        assert_eq!(
+            count(Complex::new(1.2, 0.0), Complex::new(-2.0, 0.0)),
+            [MAX_ITERS; 4]
+        );
+        assert_eq!(
+            count(Complex::new(1.2, 0.0), Complex::new(-2.0, 0.1)),
+            [MAX_ITERS; 4]
+        );
+    }
+
+    #[test]
+    fn distance_estimates_match_scalar() {
+        check_distance::<4>(rules::julia_with_distance, scalar::julia_with_distance);
+        check_distance::<8>(
+            |_z, c| rules::mandelbrot_with_distance(c),
+            |_, c| scalar::mandelbrot_with_distance(c),
+        );
+    }
+
+    #[test]
+    fn trap_distances_match_scalar() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for shape in TrapShape::ALL {
+            let trap = OrbitTrap {
+                shape,
+                center: Complex::new(0.1, -0.2),
+                size: 0.3,
+            };
+            for _ in 0..SAMPLES / 16 {
+                let zs: Array<Complex, 4> = std::array::from_fn(|_| random_complex(&mut rng));
+                let cs: Array<Complex, 4> = std::array::from_fn(|_| random_complex(&mut rng));
+                let bailout = Bailout::DEFAULT;
+                let simd_results = [
+                    rules::julia_with_trap(to_simd_complex(zs), to_simd_complex(cs), bailout, trap),
+                    rules::mandelbrot_with_trap(to_simd_complex(cs), bailout, trap),
+                ];
+                for i in 0..4 {
+                    let scalar_results = [
+                        scalar::julia_with_trap(zs[i], cs[i], bailout, trap),
+                        scalar::mandelbrot_with_trap(cs[i], bailout, trap),
+                    ];
+                    for ((distances, closest), expected) in simd_results.iter().zip(scalar_results)
+                    {
+                        let closest = Complex::new(closest.re[i], closest.im[i]);
+                        assert_eq!((distances[i], closest), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn image_trap_keeps_the_first_point_inside() {
+        let trap = OrbitTrap {
+            shape: TrapShape::Image,
+            center: Complex::new(0.0, 0.0),
+            size: 2.0,
+        };
+        // The orbit of c = -1 alternates between -1 and 0, which are both inside the trap
+        let (distance, closest) =
+            scalar::mandelbrot_with_trap(Complex::new(-1.0, 0.0), Bailout::DEFAULT, trap);
+        assert_eq!((distance, closest), (0.0, Complex::new(-1.0, 0.0)));
+    }
+
+    #[test]
+    fn derivatives_match_scalar() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..SAMPLES / 4 {
+            let zs: Array<Complex, 4> = std::array::from_fn(|_| random_complex(&mut rng));
+            let cs: Array<Complex, 4> = std::array::from_fn(|_| random_complex(&mut rng));
+            let simd_results = [
+                rules::julia_with_derivative(to_simd_complex(zs), to_simd_complex(cs)),
+                rules::mandelbrot_with_derivative(to_simd_complex(cs)),
+            ];
+            for i in 0..4 {
+                let scalar_results = [
+                    scalar::julia_with_derivative(zs[i], cs[i]),
+                    scalar::mandelbrot_with_derivative(cs[i]),
+                ];
+                for ((counts, z, dz), expected) in simd_results.iter().zip(scalar_results) {
+                    let lane = |z: &SimdComplex<4>| Complex::new(z.re[i], z.im[i]);
+                    assert_eq!((counts[i], lane(z), lane(dz)), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distance_estimate_is_close_to_true_distance() {
+        // The Mandelbrot set meets the real axis at -2 and 1/4
+        for (c, distance) in [(1.0, 0.75), (0.5, 0.25), (-2.5, 0.5), (-3.0, 1.0)] {
+            let (_, estimate) = scalar::mandelbrot_with_distance(Complex::new(c, 0.0));
+            assert!(
+                estimate > distance / 4.0 && estimate < distance * 4.0,
+                "c = {}: {} is not close to {}",
+                c,
+                estimate,
+                distance
+            );
+        }
+        // Points inside the set have no exterior distance
+        assert_eq!(
+            scalar::mandelbrot_with_distance(Complex::new(-0.1, 0.1)).1,
+            0.0
+        );
+    }
+
+    #[test]
+    fn burning_ship_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::burning_ship_julia(z, c, bailout), |z, c| {
+            scalar::burning_ship_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::burning_ship(c, bailout), |_, c| {
+            scalar::burning_ship(c, bailout)
+        });
+    }
+
+    #[test]
+    fn tricorn_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::tricorn_julia(z, c, bailout), |z, c| {
+            scalar::tricorn_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::tricorn(c, bailout), |_, c| {
+            scalar::tricorn(c, bailout)
+        });
+    }
+
+    #[test]
+    fn celtic_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::celtic_julia(z, c, bailout), |z, c| {
+            scalar::celtic_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::celtic(c, bailout), |_, c| {
+            scalar::celtic(c, bailout)
+        });
+    }
+
+    #[test]
+    fn perpendicular_mandelbrot_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::perpendicular_julia(z, c, bailout), |z, c| {
+            scalar::perpendicular_julia(z, c, bailout)
+        });
+        check_rule!(
+            |_z, c| rules::perpendicular_mandelbrot(c, bailout),
+            |_, c| { scalar::perpendicular_mandelbrot(c, bailout) }
+        );
+    }
+
+    #[test]
+    fn buffalo_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::buffalo_julia(z, c, bailout), |z, c| {
+            scalar::buffalo_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::buffalo(c, bailout), |_, c| {
+            scalar::buffalo(c, bailout)
+        });
+    }
+
+    #[test]
+    fn heart_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::heart_julia(z, c, bailout), |z, c| {
+            scalar::heart_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::heart(c, bailout), |_, c| {
+            scalar::heart(c, bailout)
+        });
+    }
+
+    #[test]
+    fn phoenix_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        let p = Complex::new(-0.5, 0.1);
+        check_rule!(
+            |z, c| rules::phoenix_julia(z, c * 0.3, SimdComplex::from_complex(p), bailout),
+            |z, c| scalar::phoenix_julia(z, c * 0.3, p, bailout)
+        );
+        check_rule!(
+            |_z, c| rules::phoenix(c, SimdComplex::from_complex(p), bailout),
+            |_, c| scalar::phoenix(c, p, bailout)
+        );
+    }
+
+    #[test]
+    fn magnet_matches_scalar() {
+        check_rule!(|z, c| rules::magnet1_julia(z, c), scalar::magnet1_julia);
+        check_rule!(|_z, c| rules::magnet1(c), |_, c| scalar::magnet1(c));
+        check_rule!(|z, c| rules::magnet2_julia(z, c), scalar::magnet2_julia);
+        check_rule!(|_z, c| rules::magnet2(c), |_, c| scalar::magnet2(c));
+    }
+
+    #[test]
+    fn magnet_stops_at_fixed_point() {
+        // For c = 1, magnet type I reduces to z = z^4 / (2z - 1)^2, which converges to 1 near 1
+        let c = SimdComplex::<4>::splat(1.0, 0.0);
+        assert!(rules::magnet1_julia(SimdComplex::splat(1.1, 0.0), c)[0] < rules::MAX_ITERS);
+        assert_eq!(rules::magnet1_julia(SimdComplex::splat(1.0, 0.0), c)[0], 0);
+    }
+
+    #[test]
+    fn lambda_matches_scalar() {
+        let bailout = Bailout::DEFAULT;
+        check_rule!(|z, c| rules::lambda_julia(z, c, bailout), |z, c| {
+            scalar::lambda_julia(z, c, bailout)
+        });
+        check_rule!(|_z, c| rules::lambda(c, bailout), |_, c| {
+            scalar::lambda(c, bailout)
+        });
+    }
+
+    #[test]
+    fn lambda_is_conjugate_to_mandelbrot() {
+        // Rounding differs between the two forms, so only chaotic orbits may disagree
+        let bailout = Bailout::DEFAULT;
+        let mut rng = fastrand::Rng::with_seed(0);
+        let mismatches = (0..SAMPLES)
+            .filter(|_| {
+                let lambda = random_complex(&mut rng);
+                let c = lambda / 2.0 - lambda * lambda / 4.0;
+                scalar::lambda(lambda, bailout) != scalar::mandelbrot(c, bailout)
+            })
+            .count();
+        assert!(mismatches < SAMPLES / 100, "{} mismatches", mismatches);
+    }
+
+    #[test]
+    fn bailout_tests_match_scalar() {
+        for test in BailoutTest::ALL {
+            for radius in [0.5, 2.0, 100.0] {
+                let bailout = Bailout { radius, test };
+                check_rule!(|z, c| rules::julia(z, c, bailout), |z, c| {
+                    scalar::julia(z, c, bailout)
+                });
+                check_rule!(|_z, c| rules::burning_ship(c, bailout), |_, c| {
+                    scalar::burning_ship(c, bailout)
+                });
+                check_rule!(
+                    |z, c| rules::phoenix_julia(z, c, c * 0.1, bailout),
+                    |z, c| scalar::phoenix_julia(z, c, c * 0.1, bailout)
+                );
+                check_rule!(|z, c| rules::lambda_julia(z, c, bailout), |z, c| {
+                    scalar::lambda_julia(z, c, bailout)
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn bailout_tests_are_ordered() {
+        // |z|_1 >= |z|_2 >= |z|_inf >= |Re z| >= min(|Re z|, |Im z|), so each test
+        // escapes no later than the next one
+        let ordered = [
+            BailoutTest::Manhattan,
+            BailoutTest::Norm,
+            BailoutTest::Max,
+            BailoutTest::Real,
+            BailoutTest::Min,
+        ];
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..SAMPLES {
+            let (z, c) = (random_complex(&mut rng), random_complex(&mut rng));
+            let counts = ordered.map(|test| scalar::julia(z, c, Bailout { radius: 2.0, test }));
+            assert!(counts.is_sorted(), "z = {}, c = {}: {:?}", z, c, counts);
+        }
+    }
+
+    #[test]
+    fn larger_radius_never_escapes_sooner() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..SAMPLES {
+            let c = random_complex(&mut rng);
+            for test in BailoutTest::ALL {
+                let small = scalar::mandelbrot(c, Bailout { radius: 2.0, test });
+                let large = scalar::mandelbrot(c, Bailout { radius: 1e3, test });
+                assert!(small <= large, "c = {}, {:?}", c, test);
+            }
+        }
+    }
+
+    #[test]
+    fn nova_matches_scalar() {
+        let params = RootFindingParams::DEFAULT;
+        check_rule!(
+            |z, c| rules::nova(z, c, params, |z| z * z * z - 1.0, |z| z * z * 3.0),
+            |z, c| scalar::nova(z, c, params, |z| z * z * z - 1.0, |z| z * z * 3.0)
+        );
+    }
+
+    #[test]
+    fn relaxed_nova_matches_scalar() {
+        let params = RootFindingParams {
+            relaxation: Complex::new(0.8, 0.3),
+            tolerance: 1e-3,
+            bailout: 10.0,
+        };
+        check_rule!(
+            |z, c| rules::nova(z, c, params, |z| z * z * z - 1.0, |z| z * z * 3.0),
+            |z, c| scalar::nova(z, c, params, |z| z * z * z - 1.0, |z| z * z * 3.0)
+        );
+    }
+
+    #[test]
+    fn averages_match_scalar() {
+        let params = RootFindingParams {
+            tolerance: 1e-3,
+            ..RootFindingParams::DEFAULT
+        };
+        let bailout = Bailout {
+            radius: 100.0,
+            ..Bailout::DEFAULT
+        };
+        for average in AVERAGES {
+            check_rule!(
+                |z, c| rules::julia_with_average(z, c, bailout, average),
+                |z, c| scalar::julia_with_average(z, c, bailout, average)
+            );
+            check_rule!(
+                |_z, c| rules::mandelbrot_with_average(c, bailout, average),
+                |_, c| scalar::mandelbrot_with_average(c, bailout, average)
+            );
+            check_rule!(
+                |z, c| {
+                    let f = |z| z * z * z - 1.0;
+                    rules::nova_with_average(z, c, params, f, |z| z * z * 3.0, average)
+                },
+                |z, c| {
+                    let f = |z| z * z * z - 1.0;
+                    scalar::nova_with_average(z, c, params, f, |z| z * z * 3.0, average)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn averages_have_no_bands() {
+        // Along this segment outside the Mandelbrot set, iteration counts go from 6 to 36
+        let bailout = Bailout {
+            radius: 1000.0,
+            ..Bailout::DEFAULT
+        };
+        let samples = 2000;
+        let points = (0..=samples)
+            .map(|i| Complex::new(-0.75, 1.0 - 0.9 * i as f64 / samples as f64))
+            .collect::<Vec<_>>();
+        let counts = points
+            .iter()
+            .map(|&c| scalar::mandelbrot(c, bailout))
+            .collect::<Vec<_>>();
+        assert!(counts[0] < 8 && counts[samples] > 30);
+        for average in AVERAGES {
+            let values = points
+                .iter()
+                .map(|&c| scalar::mandelbrot_with_average(c, bailout, average))
+                .collect::<Vec<_>>();
+            // Values jump between iteration counts no more than they change between any other samples
+            let max_jump = |between_counts: bool| {
+                (0..samples)
+                    .filter(|&i| (counts[i] != counts[i + 1]) == between_counts)
+                    .map(|i| values[i].abs_diff(values[i + 1]))
+                    .max()
+                    .unwrap()
+            };
+            assert!(
+                max_jump(true) <= max_jump(false),
+                "{:?} jumps by {} between iteration counts",
+                average,
+                max_jump(true)
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_average_interpolates_the_last_statistic() {
+        // The statistics were 0.2 and 0.6
+        let value = |fraction| smooth_average(2, 0.8, 0.6, 2.0, fraction);
+        assert_eq!(value(0.0), (0.2 * 254.0_f64).round() as u8);
+        assert_eq!(value(1.0), (0.4 * 254.0_f64).round() as u8);
+        assert_eq!(value(f64::NAN), value(1.0));
+        assert_eq!(smooth_average(MAX_ITERS, 0.8, 0.6, 2.0, 0.5), MAX_ITERS);
+        assert_eq!(escape_fraction(10.0, 10.0), 1.0);
+        assert_eq!(escape_fraction(10.0, 100.0), 0.0);
+        assert_eq!(convergence_fraction(1e-3, 1e-2, 1e-3), 1.0);
+        assert_eq!(convergence_fraction(1e-3, 1e-3, 1e-4), 0.0);
+    }
+
+    #[test]
+    fn nova_stops_at_tolerance_and_bailout() {
+        let f = |z: SimdComplex<4>| z * z * z - 1.0;
+        let df = |z: SimdComplex<4>| z * z * 3.0;
+        let z = SimdComplex::splat(1.0, 0.0);
+        let c = SimdComplex::splat(0.4, 0.1);
+        let count = |params| rules::nova(z, c, params, f, df)[0];
+
+        let coarse = RootFindingParams {
+            tolerance: 1e-2,
+            ..RootFindingParams::DEFAULT
+        };
+        assert!(count(coarse) < count(RootFindingParams::DEFAULT));
+
+        // A negative relaxation pushes the orbit away from the roots, so it only stops at the bailout
+        let diverging = RootFindingParams {
+            relaxation: Complex::new(-1.0, 0.0),
+            ..RootFindingParams::DEFAULT
+        };
+        let bounded = RootFindingParams {
+            bailout: 1e4,
+            ..diverging
+        };
+        assert_eq!(count(diverging), rules::MAX_ITERS);
+        assert!(count(bounded) < rules::MAX_ITERS);
+    }
+
+    #[test]
+    fn newton_matches_scalar() {
+        check_rule!(
+            |z, _c| rules::newton(z, |z| z * z * z - z * 2.0 + 2.0, |z| z * z * 3.0 - 2.0),
+            |z, _| scalar::newton(z, |z| z * z * z - z * 2.0 + 2.0, |z| z * z * 3.0 - 2.0)
+        );
+    }
+
+    #[test]
+    fn nova_auto_matches_scalar() {
+        let params = RootFindingParams {
+            relaxation: Complex::new(1.2, -0.1),
+            ..RootFindingParams::DEFAULT
+        };
+        check_rule!(
+            |z, c| rules::nova_auto(z, c, params, |z| z * z * z - 1.0),
+            |z, c| scalar::nova(z, c, params, |z| z * z * z - 1.0, |z| z * z * 3.0)
+        );
+    }
+
+    #[test]
+    fn newton_auto_matches_scalar() {
+        check_rule!(
+            |z, _c| rules::newton_auto(z, |z| z.powi(4) - z * 2.0 + 2.0),
+            |z, _| scalar::newton(z, |z| z.powi(4) - z * 2.0 + 2.0, |z| z.powi(3) * 4.0 - 2.0)
+        );
+    }
+
+    #[test]
+    fn halley_matches_scalar() {
+        let params = RootFindingParams {
+            relaxation: Complex::new(0.8, 0.1),
+            ..RootFindingParams::DEFAULT
+        };
+        check_rule!(
+            |z, c| rules::halley(z, c * 0.1, params, |z| z * z * z - 1.0),
+            |z, c| scalar::halley(
+                z,
+                c * 0.1,
+                params,
+                |z| z * z * z - 1.0,
+                |z| z * z * 3.0,
+                |z| z * 6.0
+            )
+            .0
+        );
+    }
+
+    #[test]
+    fn schroder_matches_scalar() {
+        let params = RootFindingParams::DEFAULT;
+        check_rule!(
+            |z, c| rules::schroder(z, c * 0.1, params, |z| z * z * z - 1.0),
+            |z, c| scalar::schroder(
+                z,
+                c * 0.1,
+                params,
+                |z| z * z * z - 1.0,
+                |z| z * z * 3.0,
+                |z| z * 6.0
+            )
+            .0
+        );
+    }
+
+    #[test]
+    fn secant_matches_scalar() {
+        let params = RootFindingParams {
+            relaxation: Complex::new(0.9, -0.2),
+            tolerance: 1e-4,
+            bailout: 100.0,
+        };
+        check_rule!(
+            |z, c| rules::secant(z, c * 0.1, params, |z| z * z * z - 1.0),
+            |z, c| scalar::secant(z, c * 0.1, params, |z| z * z * z - 1.0).0
+        );
+    }
+
+    #[test]
+    fn householder_generalizes_newton_and_halley() {
+        let params = RootFindingParams::DEFAULT;
+        let zero = SimdComplex::default();
+        let f = |z: Complex| z * z * z - 1.0;
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..SAMPLES / 8 {
+            let zs: Array<Complex, 8> = std::array::from_fn(|_| random_complex(&mut rng));
+            let z = to_simd_complex(zs);
+            let (_, newton_roots) =
+                rules::householder_with_root(z, zero, params, |z: Jet<8, 2>| z * z * z - 1.0);
+            let (_, halley_roots) =
+                rules::householder_with_root(z, zero, params, |z: Jet<8, 3>| z * z * z - 1.0);
+            for (i, &z) in zs.iter().enumerate() {
+                let (_, newton_root) =
+                    scalar::newton_with_root(z, RootFindingParams::DEFAULT, f, |z| z * z * 3.0);
+                let (_, halley_root) = scalar::halley(
+                    z,
+                    Complex::default(),
+                    params,
+                    f,
+                    |z| z * z * 3.0,
+                    |z| z * 6.0,
+                );
+                // Only compare pixels that converged, chaotic orbits amplify rounding differences
+                for (roots, expected) in [(newton_roots, newton_root), (halley_roots, halley_root)]
+                {
+                    let root = Complex::new(roots.re[i], roots.im[i]);
+                    if f(expected).norm_sqr() < 1e-12 && f(root).norm_sqr() < 1e-12 {
+                        assert!(
+                            (root - expected).norm_sqr() < 1e-12,
+                            "z = {}: {} != {}",
+                            z,
+                            root,
+                            expected
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn higher_order_householder_converges_faster() {
+        let params = RootFindingParams::DEFAULT;
+        let zero = SimdComplex::<4>::default();
+        let z = SimdComplex::splat(2.0, 1.5);
+        let order_1 = rules::householder(z, zero, params, |z: Jet<4, 2>| z.powi(5) - 1.0);
+        let order_4 = rules::householder(z, zero, params, |z: Jet<4, 5>| z.powi(5) - 1.0);
+        assert!(order_4[0] < order_1[0], "{} >= {}", order_4[0], order_1[0]);
+    }
+
+    #[test]
+    fn schroder_converges_quickly_to_multiple_roots() {
+        let params = RootFindingParams::DEFAULT;
+        let zero = SimdComplex::<4>::default();
+        let z = SimdComplex::splat(2.0, 0.5);
+        // Triple root at 1
+        let f = |z: Jet<4, 3>| (z - 1.0).powi(3) * (z + 2.0);
+        let newton = rules::householder(z, zero, params, |z: Jet<4, 2>| {
+            (z - 1.0).powi(3) * (z + 2.0)
+        });
+        let (schroder, root) = rules::schroder_with_root(z, zero, params, f);
+        assert!(schroder[0] < newton[0], "{} >= {}", schroder[0], newton[0]);
+        assert!((root - SimdComplex::splat(1.0, 0.0)).norm_squared()[0] < 1e-10);
+    }
+
+    #[test]
+    fn newton_roots_match_scalar() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        let params = RootFindingParams {
+            relaxation: Complex::new(0.9, 0.1),
+            ..RootFindingParams::DEFAULT
+        };
+        for _ in 0..SAMPLES / 8 {
+            let zs: Array<Complex, 8> = std::array::from_fn(|_| random_complex(&mut rng));
+            let (counts, roots) = rules::newton_with_root(
+                to_simd_complex(zs),
+                params,
+                |z| z * z * z - 1.0,
+                |z| z * z * 3.0,
+            );
+            for (i, &z) in zs.iter().enumerate() {
+                let (expected_count, expected_root) =
+                    scalar::newton_with_root(z, params, |z| z * z * z - 1.0, |z| z * z * 3.0);
+                let root = Complex::new(roots.re[i], roots.im[i]);
+                assert_eq!(counts[i], expected_count, "z = {}", z);
+                assert!(
+                    (root - expected_root).norm_sqr() < 1e-20,
+                    "z = {}: {} != {}",
+                    z,
+                    root,
+                    expected_root
+                );
+            }
+        }
+    }
+
+    /// Checks a SIMD rule against its scalar reference for every supported lane count.
+    /// The SIMD rule is written as a closure pattern so that it can be instantiated
+    /// once per lane count.
+    macro_rules! check_rule {
+        (|$z:ident, $c:ident| $simd_rule:expr, $scalar_rule:expr) => {
+            check_rule!(|$z, $c| $simd_rule, $scalar_rule, lanes = [2, 4, 8, 16]);
+        };
+        (|$z:ident, $c:ident| $simd_rule:expr, $scalar_rule:expr, lanes = [$($n:literal),+]) => {
+            $(
+                check_lanes::<$n>(|$z, $c| $simd_rule, $scalar_rule);
+            )+
+        };
+    }
+    use check_rule;
+
+    fn check_lanes<const N: usize>(
+        simd_rule: impl Fn(SimdComplex<N>, SimdComplex<N>) -> Array<u8, N>,
+        scalar_rule: impl Fn(Complex, Complex) -> u8,
+    ) {
+        let mut rng = fastrand::Rng::with_seed(N as u64);
+        for _ in 0..SAMPLES / N {
+            let zs: Array<Complex, N> = std::array::from_fn(|_| random_complex(&mut rng));
+            let cs: Array<Complex, N> = std::array::from_fn(|_| random_complex(&mut rng));
+            let expected: Array<u8, N> = std::array::from_fn(|i| scalar_rule(zs[i], cs[i]));
+            assert_eq!(
+                simd_rule(to_simd_complex(zs), to_simd_complex(cs)),
+                expected,
+                "z = {:?}, c = {:?}",
+                zs,
+                cs
+            );
+        }
+    }
+
+    fn check_distance<const N: usize>(
+        simd_rule: impl Fn(SimdComplex<N>, SimdComplex<N>) -> (Array<u8, N>, SimdDouble<N>),
+        scalar_rule: impl Fn(Complex, Complex) -> (u8, f64),
+    ) {
+        let mut rng = fastrand::Rng::with_seed(N as u64);
+        for _ in 0..SAMPLES / N {
+            let zs: Array<Complex, N> = std::array::from_fn(|_| random_complex(&mut rng));
+            let cs: Array<Complex, N> = std::array::from_fn(|_| random_complex(&mut rng));
+            let (counts, distances) = simd_rule(to_simd_complex(zs), to_simd_complex(cs));
+            for i in 0..N {
+                let (count, distance) = scalar_rule(zs[i], cs[i]);
+                assert_eq!(counts[i], count, "z = {}, c = {}", zs[i], cs[i]);
+                assert!(
+                    (distances[i] - distance).abs() <= distance * 1e-12,
+                    "z = {}, c = {}: {} != {}",
+                    zs[i],
+                    cs[i],
+                    distances[i],
+                    distance
+                );
+            }
+        }
+    }
+
+    fn random_complex(rng: &mut fastrand::Rng) -> Complex {
+        let mut sample = || (rng.f64() * 2.0 - 1.0) * SAMPLE_RADIUS;
+        Complex::new(sample(), sample())
+    }
+
+    fn to_simd_complex<const N: usize>(values: Array<Complex, N>) -> SimdComplex<N> {
+        SimdComplex {
+            re: values.map(|z| z.re).into(),
+            im: values.map(|z| z.im).into(),
+        }
+    }
 }
@@ -1,55 +1,76 @@
 use crate::utils::Complex;
 use nalgebra::ComplexField;
 use std::{
-    ops::{Add, Div, Mul, Sub},
-    simd::{Mask, Simd},
+    ops::{Add, Div, Mul, Neg, Sub},
+    simd::{
+        cmp::{SimdPartialEq, SimdPartialOrd},
+        num::SimdFloat,
+        Mask, Select, Simd, StdFloat,
+    },
 };
 
-/// SIMD parallelization factor, chosen empirically.
+/// Default SIMD parallelization factor, chosen empirically.
+/// All SIMD types are generic over the lane count and fall back to this value.
 pub const SIMD_LEN: usize = 8;
 
 /// Array with size equal to SIMD vector length
-pub type Array<T> = [T; SIMD_LEN];
+pub type Array<T, const N: usize = SIMD_LEN> = [T; N];
 
-pub type SimdDouble = Simd<f64, SIMD_LEN>;
+pub type SimdDouble<const N: usize = SIMD_LEN> = Simd<f64, N>;
 
+/// Per-lane iteration counter.
+/// Once a lane stops being incremented, it is never incremented again,
+/// which keeps the result of each lane independent of the other lanes.
 #[derive(Debug, Clone)]
-pub struct SimdCounter {
-    counts: Simd<i64, SIMD_LEN>,
+pub struct SimdCounter<const N: usize = SIMD_LEN> {
+    counts: Simd<i64, N>,
+    active: Mask<i64, N>,
     modified: bool,
 }
 
-impl SimdCounter {
+impl<const N: usize> SimdCounter<N> {
     pub fn new() -> Self {
         Self {
-            counts: Simd::<i64, SIMD_LEN>::splat(0),
+            counts: Simd::<i64, N>::splat(0),
+            active: Mask::splat(true),
             modified: false,
         }
     }
 
-    pub fn increment_where(&mut self, mask: Mask<i64, SIMD_LEN>) {
+    pub fn increment_where(&mut self, mask: Mask<i64, N>) {
+        self.active &= mask;
         // Subtract because true is converted to -1
-        self.counts = self.counts - mask.to_int();
-        self.modified = mask.any();
+        self.counts -= self.active.to_simd();
+        self.modified = self.active.any();
     }
 
-    pub fn counts(&self) -> Array<u8> {
+    /// Lanes that have not stopped counting yet.
+    pub fn active(&self) -> Mask<i64, N> {
+        self.active
+    }
+
+    pub fn counts(&self) -> Array<u8, N> {
         self.counts.as_array().map(|x| x as u8)
     }
 
+    /// Same as `counts`, for counters that go beyond `u8::MAX`.
+    pub fn wide_counts(&self) -> Array<u32, N> {
+        self.counts.as_array().map(|x| x as u32)
+    }
+
     pub fn modified(&self) -> bool {
         self.modified
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct SimdComplex {
-    pub re: SimdDouble,
-    pub im: SimdDouble,
+pub struct SimdComplex<const N: usize = SIMD_LEN> {
+    pub re: SimdDouble<N>,
+    pub im: SimdDouble<N>,
 }
 
-impl SimdComplex {
-    pub const LEN: usize = SimdDouble::LEN;
+impl<const N: usize> SimdComplex<N> {
+    pub const LEN: usize = N;
 
     pub fn splat(re: f64, im: f64) -> Self {
         Self {
@@ -62,19 +83,183 @@ impl SimdComplex {
         Self::splat(z.real(), z.imaginary())
     }
 
-    pub fn norm_squared(&self) -> SimdDouble {
+    /// Picks each lane from `if_true` where the mask is set, and from `if_false` otherwise.
+    pub fn select(mask: Mask<i64, N>, if_true: Self, if_false: Self) -> Self {
+        Self {
+            re: mask.select(if_true.re, if_false.re),
+            im: mask.select(if_true.im, if_false.im),
+        }
+    }
+
+    pub fn norm_squared(&self) -> SimdDouble<N> {
         self.re * self.re + self.im * self.im
     }
+
+    pub fn conj(self) -> Self {
+        Self {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+
+    /// Takes the absolute value of both the real and the imaginary part.
+    pub fn abs_parts(self) -> Self {
+        Self {
+            re: self.re.abs(),
+            im: self.im.abs(),
+        }
+    }
+
+    /// Takes the absolute value of the real part.
+    pub fn abs_re(self) -> Self {
+        Self {
+            re: self.re.abs(),
+            im: self.im,
+        }
+    }
+
+    pub fn from_polar(r: SimdDouble<N>, theta: SimdDouble<N>) -> Self {
+        Self {
+            re: r * theta.cos(),
+            im: r * theta.sin(),
+        }
+    }
+
+    /// Returns the modulus of each lane.
+    pub fn abs(&self) -> SimdDouble<N> {
+        zip_lanes(self.re, self.im, f64::hypot)
+    }
+
+    /// Returns the argument of each lane, in the range `(-pi, pi]`.
+    pub fn arg(&self) -> SimdDouble<N> {
+        zip_lanes(self.im, self.re, f64::atan2)
+    }
+
+    pub fn exp(self) -> Self {
+        Self::from_polar(self.re.exp(), self.im)
+    }
+
+    /// Natural logarithm, using the principal branch.
+    pub fn ln(self) -> Self {
+        Self {
+            re: self.abs().ln(),
+            im: self.arg(),
+        }
+    }
+
+    /// Principal square root.
+    pub fn sqrt(self) -> Self {
+        Self::from_polar(self.abs().sqrt(), self.arg() * SimdDouble::splat(0.5))
+    }
+
+    pub fn sin(self) -> Self {
+        Self {
+            re: self.re.sin() * map_lanes(self.im, f64::cosh),
+            im: self.re.cos() * map_lanes(self.im, f64::sinh),
+        }
+    }
+
+    pub fn cos(self) -> Self {
+        Self {
+            re: self.re.cos() * map_lanes(self.im, f64::cosh),
+            im: -self.re.sin() * map_lanes(self.im, f64::sinh),
+        }
+    }
+
+    pub fn tan(self) -> Self {
+        let two_re = self.re + self.re;
+        let two_im = self.im + self.im;
+        let denominator = two_re.cos() + map_lanes(two_im, f64::cosh);
+        Self {
+            re: two_re.sin() / denominator,
+            im: map_lanes(two_im, f64::sinh) / denominator,
+        }
+    }
+
+    pub fn sinh(self) -> Self {
+        Self {
+            re: map_lanes(self.re, f64::sinh) * self.im.cos(),
+            im: map_lanes(self.re, f64::cosh) * self.im.sin(),
+        }
+    }
+
+    pub fn cosh(self) -> Self {
+        Self {
+            re: map_lanes(self.re, f64::cosh) * self.im.cos(),
+            im: map_lanes(self.re, f64::sinh) * self.im.sin(),
+        }
+    }
+
+    /// Raises each lane to an integer power using repeated squaring.
+    pub fn powi(self, exp: u32) -> Self {
+        if exp == 0 {
+            return Self::splat(1.0, 0.0);
+        }
+        let mut base = self;
+        let mut exp = exp;
+        while exp & 1 == 0 {
+            base = base * base;
+            exp >>= 1;
+        }
+        let mut res = base;
+        exp >>= 1;
+        while exp > 0 {
+            base = base * base;
+            if exp & 1 == 1 {
+                res = res * base;
+            }
+            exp >>= 1;
+        }
+        res
+    }
+
+    /// Raises each lane to a real power.
+    pub fn powf(self, exp: f64) -> Self {
+        let exp = SimdDouble::splat(exp);
+        Self::from_polar(zip_lanes(self.abs(), exp, f64::powf), self.arg() * exp)
+    }
+
+    /// Raises each lane to a complex power.
+    /// Zero lanes give zero when the real part of the exponent is positive, like `powf`.
+    pub fn powc(self, exp: Self) -> Self {
+        let r = self.abs();
+        let theta = self.arg();
+        let power = Self::from_polar(
+            zip_lanes(r, exp.re, f64::powf) * (-exp.im * theta).exp(),
+            exp.re * theta + exp.im * r.ln(),
+        );
+        // The logarithm of zero would make the angle infinite, and the power NaN
+        let zero = SimdDouble::splat(0.0);
+        Self::select(
+            r.simd_eq(zero) & exp.re.simd_gt(zero),
+            Self::default(),
+            power,
+        )
+    }
+}
+
+/// Applies a scalar function lane by lane, for operations not provided by `StdFloat`.
+fn map_lanes<const N: usize>(x: SimdDouble<N>, f: impl Fn(f64) -> f64) -> SimdDouble<N> {
+    SimdDouble::from_array(x.to_array().map(f))
+}
+
+/// Binary version of `map_lanes`.
+fn zip_lanes<const N: usize>(
+    lhs: SimdDouble<N>,
+    rhs: SimdDouble<N>,
+    f: impl Fn(f64, f64) -> f64,
+) -> SimdDouble<N> {
+    SimdDouble::from_array(std::array::from_fn(|i| f(lhs[i], rhs[i])))
 }
 
-impl Default for SimdComplex {
+impl<const N: usize> Default for SimdComplex<N> {
     fn default() -> Self {
         Self::splat(0.0, 0.0)
     }
 }
 
-impl Add for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Add for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn add(self, rhs: Self) -> Self::Output {
         Self {
@@ -84,8 +269,8 @@ impl Add for SimdComplex {
     }
 }
 
-impl Add<f64> for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Add<f64> for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn add(self, rhs: f64) -> Self::Output {
         Self {
@@ -95,8 +280,19 @@ impl Add<f64> for SimdComplex {
     }
 }
 
-impl Sub for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Neg for SimdComplex<N> {
+    type Output = SimdComplex<N>;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            re: -self.re,
+            im: -self.im,
+        }
+    }
+}
+
+impl<const N: usize> Sub for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn sub(self, rhs: Self) -> Self::Output {
         Self {
@@ -106,8 +302,8 @@ impl Sub for SimdComplex {
     }
 }
 
-impl Sub<f64> for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Sub<f64> for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn sub(self, rhs: f64) -> Self::Output {
         Self {
@@ -117,8 +313,8 @@ impl Sub<f64> for SimdComplex {
     }
 }
 
-impl Mul for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Mul for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn mul(self, rhs: Self) -> Self::Output {
         Self {
@@ -128,8 +324,8 @@ impl Mul for SimdComplex {
     }
 }
 
-impl Mul<f64> for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Mul<f64> for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn mul(self, rhs: f64) -> Self::Output {
         let factor = SimdDouble::splat(rhs);
@@ -140,8 +336,8 @@ impl Mul<f64> for SimdComplex {
     }
 }
 
-impl Div for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Div for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn div(self, rhs: Self) -> Self::Output {
         let norm_sqr = rhs.norm_squared();
@@ -154,8 +350,8 @@ impl Div for SimdComplex {
     }
 }
 
-impl Div<f64> for SimdComplex {
-    type Output = SimdComplex;
+impl<const N: usize> Div<f64> for SimdComplex<N> {
+    type Output = SimdComplex<N>;
 
     fn div(self, rhs: f64) -> Self::Output {
         let factor = SimdDouble::splat(rhs);
@@ -170,6 +366,64 @@ impl Div<f64> for SimdComplex {
 mod tests {
     use crate::{simd::SimdComplex, utils::Complex};
     use itertools::Itertools;
+    use nalgebra::ComplexField;
+
+    /// Maximum distance in units in the last place from the `nalgebra` result.
+    const MAX_ULPS: u64 = 4;
+
+    /// Compares a SIMD function with its `nalgebra` counterpart on all lanes.
+    /// Complex arguments are splatted into SIMD vectors.
+    macro_rules! check_fn {
+        ($f:ident, $z:expr $(, $arg:expr)*) => {
+            let expected = $z.$f($($arg),*);
+            let actual = SimdComplex::<8>::from_complex($z).$f($(IntoSimdArg::into_simd_arg($arg)),*);
+            for i in 0..SimdComplex::<8>::LEN {
+                assert_within_ulps(actual.re[i], expected.re);
+                assert_within_ulps(actual.im[i], expected.im);
+            }
+        };
+    }
+
+    trait IntoSimdArg<T> {
+        fn into_simd_arg(self) -> T;
+    }
+
+    impl IntoSimdArg<f64> for f64 {
+        fn into_simd_arg(self) -> f64 {
+            self
+        }
+    }
+
+    impl IntoSimdArg<SimdComplex<8>> for Complex {
+        fn into_simd_arg(self) -> SimdComplex<8> {
+            SimdComplex::from_complex(self)
+        }
+    }
+
+    fn assert_within_ulps(actual: f64, expected: f64) {
+        let same_nan = actual.is_nan() && expected.is_nan();
+        assert!(
+            same_nan || ulp_distance(actual, expected) <= MAX_ULPS,
+            "{} differs from {} by more than {} ULPs",
+            actual,
+            expected,
+            MAX_ULPS
+        );
+    }
+
+    /// Distance between two doubles in representable values,
+    /// with both zeros considered equal.
+    fn ulp_distance(lhs: f64, rhs: f64) -> u64 {
+        let ordered = |x: f64| {
+            let bits = x.to_bits() as i64;
+            if bits < 0 {
+                i64::MIN - bits
+            } else {
+                bits
+            }
+        };
+        ordered(lhs).abs_diff(ordered(rhs))
+    }
 
     #[test]
     fn addition_works() {
@@ -199,7 +453,7 @@ mod tests {
 
     #[test]
     fn division_by_one_works() {
-        check_division(Complex::new(3.14, 2.78), Complex::new(1.0, 0.0));
+        check_division(Complex::new(3.25, 2.75), Complex::new(1.0, 0.0));
     }
 
     #[test]
@@ -209,6 +463,116 @@ mod tests {
         }
     }
 
+    #[test]
+    fn conjugate_works() {
+        for z in complex_numbers_with_zero() {
+            assert_eq!(
+                SimdComplex::<8>::from_complex(z).conj(),
+                SimdComplex::from_complex(z.conj())
+            );
+        }
+    }
+
+    #[test]
+    fn abs_parts_works() {
+        for z in complex_numbers_with_zero() {
+            let simd_z = SimdComplex::<8>::from_complex(z);
+            assert_eq!(
+                simd_z.abs_parts(),
+                SimdComplex::from_complex(Complex::new(z.re.abs(), z.im.abs()))
+            );
+            assert_eq!(
+                simd_z.abs_re(),
+                SimdComplex::from_complex(Complex::new(z.re.abs(), z.im))
+            );
+        }
+    }
+
+    #[test]
+    fn powi_works() {
+        for z in complex_numbers_with_zero() {
+            for exp in 0..8 {
+                let expected = (0..exp).fold(Complex::new(1.0, 0.0), |acc, _| acc * z);
+                let actual = SimdComplex::<8>::from_complex(z).powi(exp);
+                for i in 0..8 {
+                    let error = (Complex::new(actual.re[i], actual.im[i]) - expected).modulus();
+                    assert!(
+                        error <= expected.modulus() * 1e-14,
+                        "{}^{} = {}, expected {}",
+                        z,
+                        exp,
+                        Complex::new(actual.re[i], actual.im[i]),
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn abs_and_arg_work() {
+        for z in complex_numbers_with_zero() {
+            let simd_z = SimdComplex::<8>::from_complex(z);
+            assert_within_ulps(simd_z.abs()[0], z.modulus());
+            assert_within_ulps(simd_z.arg()[0], z.argument());
+        }
+    }
+
+    #[test]
+    fn exp_works() {
+        for z in complex_numbers_with_zero() {
+            check_fn!(exp, z);
+        }
+    }
+
+    #[test]
+    fn ln_works() {
+        for z in complex_numbers_without_zero() {
+            check_fn!(ln, z);
+        }
+    }
+
+    #[test]
+    fn sqrt_works() {
+        for z in complex_numbers_with_zero() {
+            check_fn!(sqrt, z);
+        }
+    }
+
+    #[test]
+    fn trigonometric_functions_work() {
+        for z in complex_numbers_with_zero() {
+            check_fn!(sin, z);
+            check_fn!(cos, z);
+            check_fn!(tan, z);
+        }
+    }
+
+    #[test]
+    fn hyperbolic_functions_work() {
+        for z in complex_numbers_with_zero() {
+            check_fn!(sinh, z);
+            check_fn!(cosh, z);
+        }
+    }
+
+    #[test]
+    fn powf_works() {
+        for z in complex_numbers_without_zero() {
+            for exp in [-1.5, 0.5, 2.0, 3.7] {
+                check_fn!(powf, z, exp);
+            }
+        }
+    }
+
+    #[test]
+    fn powc_works() {
+        for (z, exp) in complex_numbers_without_zero().tuple_windows() {
+            let exp = exp / 4.0;
+            check_fn!(powc, z, exp);
+        }
+    }
+
     fn complex_numbers_without_zero() -> impl Iterator<Item = Complex> {
         (-10..10)
             .cartesian_product(-10..10)
@@ -222,13 +586,18 @@ mod tests {
 
     macro_rules! check_op {
         ($op:tt, $lhs:expr, $rhs:expr) => {
-            let simd_lhs = SimdComplex::from_complex($lhs);
-            let simd_rhs = SimdComplex::from_complex($rhs);
-            assert_eq!(
-                simd_lhs $op simd_rhs,
-                SimdComplex::from_complex($lhs $op $rhs)
-            );
-        }
+            check_op!($op, $lhs, $rhs, lanes = [2, 4, 8, 16]);
+        };
+        ($op:tt, $lhs:expr, $rhs:expr, lanes = [$($n:literal),+]) => {
+            $(
+                let simd_lhs = SimdComplex::<$n>::from_complex($lhs);
+                let simd_rhs = SimdComplex::<$n>::from_complex($rhs);
+                assert_eq!(
+                    simd_lhs $op simd_rhs,
+                    SimdComplex::<$n>::from_complex($lhs $op $rhs)
+                );
+            )+
+        };
     }
 
     fn check_addition(lhs: Complex, rhs: Complex) {
@@ -17,11 +17,17 @@ impl ComplexPlaneView {
     const SCALE_FACTOR: f64 = 0.85;
 
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_offset_and_scale(width, height, Self::INITIAL_OFFSET, Self::INITIAL_SCALE)
+    }
+
+    /// Creates a view with a fixed position in the Complex plane.
+    /// The scale is the length of the smallest dimension of the view.
+    pub fn with_offset_and_scale(width: usize, height: usize, offset: Complex, scale: f64) -> Self {
         Self {
             width,
             height,
-            offset: Self::INITIAL_OFFSET,
-            scale: Self::INITIAL_SCALE,
+            offset,
+            scale,
         }
     }
 
@@ -41,10 +47,14 @@ impl ComplexPlaneView {
         self.offset
     }
 
+    /// Returns the length of a pixel side in the Complex plane.
+    pub fn pixel_size(&self) -> f64 {
+        self.scale / std::cmp::min(self.width, self.height) as f64
+    }
+
     /// Creates a function that maps pixel coordinates to Complex plane coordinates
     pub fn pixel_mapper(&self) -> impl Fn(usize, usize) -> Complex {
-        let smallest_dimension = std::cmp::min(self.width, self.height) as f64;
-        let pixel_scale = self.scale / smallest_dimension;
+        let pixel_scale = self.pixel_size();
         let half_width = self.width as f64 * 0.5;
         let half_height = self.height as f64 * 0.5;
         let offset = self.offset;
@@ -58,6 +68,28 @@ impl ComplexPlaneView {
         }
     }
 
+    /// Creates the inverse of `pixel_mapper`, which maps a Complex number to the pixel
+    /// whose coordinates are nearest, or `None` if it falls outside of the view.
+    pub fn pixel_locator(&self) -> impl Fn(Complex) -> Option<(usize, usize)> {
+        let inverse_pixel_scale = self.pixel_size().recip();
+        let half_width = self.width as f64 * 0.5;
+        let half_height = self.height as f64 * 0.5;
+        let (width, height) = (self.width as f64, self.height as f64);
+        let offset = self.offset;
+
+        move |z| {
+            let z = z - offset;
+            let x = (half_width + z.re * inverse_pixel_scale + 0.5).floor();
+            let y = (half_height - z.im * inverse_pixel_scale + 0.5).floor();
+            // Also rejects NaN
+            if (0.0..width).contains(&x) && (0.0..height).contains(&y) {
+                Some((x as usize, y as usize))
+            } else {
+                None
+            }
+        }
+    }
+
     pub fn translate(&mut self, direction: Direction) {
         self.offset += direction.as_complex() * Self::BASE_OFFSET_STEP * self.scale;
     }